@@ -0,0 +1,176 @@
+extern crate criterion;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use dog_food_for_you::{
+    generate_source_data, get_line, write_file_with_progress, CsvOptions, Graph, InputFilenames,
+    Mapping, NodeType, OutputOptions, ProgressUpdate, SourceDataOptions, WalkOptions,
+};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::env;
+use std::fs;
+
+/// Source files for `num_dogs` dogs and as many foods, with the default lines per node.
+struct Fixture {
+    dog_food: Vec<u8>,
+    food_ingredient: Vec<u8>,
+    ingredient_flavor: Vec<u8>,
+}
+
+impl Fixture {
+    /// Every fixture, and every walk, comes from this seed, so results can be compared between
+    /// commits.
+    const SEED: u64 = 2020;
+
+    fn new(num_dogs: usize) -> Self {
+        let options = SourceDataOptions {
+            num_dogs,
+            num_foods: num_dogs,
+            num_ingredients: (num_dogs / 10).max(1),
+            ..SourceDataOptions::default()
+        };
+        let mut fixture = Fixture {
+            dog_food: Vec::new(),
+            food_ingredient: Vec::new(),
+            ingredient_flavor: Vec::new(),
+        };
+        generate_source_data(
+            &options,
+            &mut StdRng::seed_from_u64(Fixture::SEED),
+            &mut fixture.dog_food,
+            &mut fixture.food_ingredient,
+            &mut fixture.ingredient_flavor,
+        )
+        .unwrap();
+        fixture
+    }
+
+    fn len(&self) -> usize {
+        self.dog_food.len() + self.food_ingredient.len() + self.ingredient_flavor.len()
+    }
+
+    fn mapping(&self) -> Mapping {
+        Mapping::new(
+            self.dog_food.as_slice(),
+            self.food_ingredient.as_slice(),
+            self.ingredient_flavor.as_slice(),
+        )
+    }
+}
+
+/// The first 100 nodes of a type by name, so every run samples from the same nodes.
+fn sorted_nodes(mapping: &Mapping, node_type: NodeType) -> Vec<&str> {
+    let mut nodes: Vec<&str> = mapping.nodes(node_type).map(String::as_str).collect();
+    nodes.sort_unstable();
+    nodes.truncate(100);
+    nodes
+}
+
+fn bench_mapping_new(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Mapping::new");
+    group.sample_size(10);
+    for &num_dogs in &[500, 2000, 5000] {
+        let fixture = Fixture::new(num_dogs);
+        group.throughput(Throughput::Bytes(fixture.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(num_dogs),
+            &fixture,
+            |b, fixture| b.iter(|| fixture.mapping()),
+        );
+    }
+    group.finish();
+}
+
+fn bench_get_line(c: &mut Criterion) {
+    let mapping = Fixture::new(2000).mapping();
+    let dogs = sorted_nodes(&mapping, NodeType::Dog);
+
+    let mut group = c.benchmark_group("get_line");
+    for &walks_per_line in &[1u8, 4, 16] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(walks_per_line),
+            &walks_per_line,
+            |b, &walks_per_line| {
+                let mut rng = StdRng::seed_from_u64(Fixture::SEED);
+                let mut index = 0;
+                b.iter(|| {
+                    index = (index + 1) % dogs.len();
+                    get_line(walks_per_line, dogs[index], &mapping, &mut rng)
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Benchmarks one sampler, cycling through nodes of the type it samples from.
+macro_rules! bench_sampler {
+    ($group:expr, $mapping:expr, $sampler:ident, $node_type:expr) => {{
+        let nodes = sorted_nodes(&$mapping, $node_type);
+        let mut rng = StdRng::seed_from_u64(Fixture::SEED);
+        let mut index = 0;
+        $group.bench_function(stringify!($sampler), |b| {
+            b.iter(|| {
+                index = (index + 1) % nodes.len();
+                $mapping.$sampler(nodes[index], &mut rng)
+            })
+        });
+    }};
+}
+
+fn bench_samplers(c: &mut Criterion) {
+    let mapping = Fixture::new(2000).mapping();
+
+    let mut group = c.benchmark_group("samplers");
+    bench_sampler!(group, mapping, food_liked_by_dog, NodeType::Dog);
+    bench_sampler!(group, mapping, ingredient_in_food, NodeType::Food);
+    bench_sampler!(group, mapping, flavor_for_ingredient, NodeType::Ingredient);
+    bench_sampler!(group, mapping, ingredient_with_flavor, NodeType::Flavor);
+    bench_sampler!(group, mapping, food_with_ingredient, NodeType::Ingredient);
+    bench_sampler!(group, mapping, dog_that_likes_food, NodeType::Food);
+    group.finish();
+}
+
+fn bench_write_file(c: &mut Criterion) {
+    let fixture = Fixture::new(500);
+    let directory = env::temp_dir().join("dog_food_for_you_bench");
+    fs::create_dir_all(&directory).unwrap();
+    let path = |filename: &str| directory.join(filename).to_str().unwrap().to_owned();
+    let inputs = InputFilenames::new(
+        &path("dog_food.csv"),
+        &path("food_ingredient.csv"),
+        &path("ingredient_flavor.csv"),
+    );
+    fs::write(&inputs.dog_food, &fixture.dog_food).unwrap();
+    fs::write(&inputs.food_ingredient, &fixture.food_ingredient).unwrap();
+    fs::write(&inputs.ingredient_flavor, &fixture.ingredient_flavor).unwrap();
+    let output = OutputOptions::new(&path("output.txt"));
+    let walks = WalkOptions {
+        seed: Some(Fixture::SEED),
+        ..WalkOptions::new(10, 4)
+    };
+
+    let mut group = c.benchmark_group("write_file");
+    group.sample_size(10);
+    group.bench_function("500 dogs", |b| {
+        b.iter(|| {
+            write_file_with_progress(
+                &inputs,
+                &output,
+                &walks,
+                &CsvOptions::default(),
+                &mut |_: &ProgressUpdate| {},
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_mapping_new,
+    bench_get_line,
+    bench_samplers,
+    bench_write_file
+);
+criterion_main!(benches);
@@ -0,0 +1,58 @@
+extern crate structopt;
+
+use dog_food_for_you::{files, CsvArgs, MappedGraph, Mapping};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::Instant;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "build_graph",
+    about = "Writes the source files as a graph file that serve can memory-map"
+)]
+struct Opt {
+    #[structopt(short = "o", long = "output-file", default_value = "graph.bin")]
+    output_filename: String,
+
+    #[structopt(long = "dog-food-file", default_value = "dog_food_lines.csv")]
+    dog_food_filename: String,
+
+    #[structopt(
+        long = "food-ingredients-file",
+        default_value = "food_ingredient_lines.csv"
+    )]
+    food_ingredients_filename: String,
+
+    #[structopt(
+        long = "ingredients-flavor-file",
+        default_value = "ingredient_flavor_lines.csv"
+    )]
+    ingredients_flavor_filename: String,
+
+    #[structopt(flatten)]
+    csv: CsvArgs,
+}
+
+fn main() {
+    let start = Instant::now();
+    let opt = Opt::from_args();
+
+    let csv_options = opt.csv.into_options();
+    let mapping = Mapping::with_options(
+        files::open(&opt.dog_food_filename).unwrap(),
+        files::open(&opt.food_ingredients_filename).unwrap(),
+        files::open(&opt.ingredients_flavor_filename).unwrap(),
+        &csv_options,
+    );
+
+    let mut file = BufWriter::new(File::create(&opt.output_filename).unwrap());
+    MappedGraph::write(&mapping, &mut file).unwrap();
+    file.flush().unwrap();
+
+    eprintln!(
+        "Wrote {} in {}ms",
+        opt.output_filename,
+        start.elapsed().as_millis()
+    );
+}
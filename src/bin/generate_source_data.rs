@@ -1,11 +1,10 @@
 extern crate rand;
 extern crate structopt;
 
-use rand::distributions::Alphanumeric;
-use rand::seq::SliceRandom;
-use rand::{thread_rng, Rng};
-use std::fs::File;
-use std::io::{BufWriter, Write};
+use dog_food_for_you::{files, generate_source_data, SourceDataOptions};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::io::{BufWriter, IntoInnerError};
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -39,7 +38,35 @@ struct Opt {
         default_value = "10"
     )]
     num_ingredient_flavor_lines: u8,
+
+    #[structopt(
+        long = "dog-food-file",
+        help = "Compressed if it ends in .gz or .zst.",
+        default_value = "dog_food_lines.csv"
+    )]
+    dog_food_filename: String,
+
+    #[structopt(
+        long = "food-ingredients-file",
+        help = "Compressed if it ends in .gz or .zst.",
+        default_value = "food_ingredient_lines.csv"
+    )]
+    food_ingredients_filename: String,
+
+    #[structopt(
+        long = "ingredients-flavor-file",
+        help = "Compressed if it ends in .gz or .zst.",
+        default_value = "ingredient_flavor_lines.csv"
+    )]
+    ingredients_flavor_filename: String,
+
+    #[structopt(
+        long = "seed",
+        help = "Makes the same data every time for the same seed and sizes."
+    )]
+    seed: Option<u64>,
 }
+
 fn main() {
     let opt = Opt::from_args();
 
@@ -56,57 +83,38 @@ fn main() {
         panic!("num-flavors must be at least 1")
     }
 
-    let rng = thread_rng();
-    let dogs: Vec<String> = (0..opt.num_dogs)
-        .map(|_| rng.sample_iter(&Alphanumeric).take(10).collect::<String>())
-        .map(|id| format!("dog-{}", id))
-        .collect();
-    let foods: Vec<String> = (0..opt.num_foods)
-        .map(|_| rng.sample_iter(&Alphanumeric).take(10).collect::<String>())
-        .map(|id| format!("food-{}", id))
-        .collect();
-    let ingredients: Vec<String> = (0..opt.num_ingredients)
-        .map(|_| rng.sample_iter(&Alphanumeric).take(10).collect::<String>())
-        .map(|id| format!("ingredient-{}", id))
-        .collect();
-    let flavors: Vec<String> = (0..opt.num_flavors)
-        .map(|_| rng.sample_iter(&Alphanumeric).take(10).collect::<String>())
-        .map(|id| format!("flavor-{}", id))
-        .collect();
-
-    write_association(
-        "dog_food_lines.csv",
-        &dogs,
-        &foods,
-        opt.num_dog_food_lines as usize,
-    );
-    write_association(
-        "food_ingredient_lines.csv",
-        &foods,
-        &ingredients,
-        opt.num_food_ingredient_lines as usize,
-    );
-    write_association(
-        "ingredient_flavor_lines.csv",
-        &ingredients,
-        &flavors,
-        opt.num_ingredient_flavor_lines as usize,
-    );
-}
+    let options = SourceDataOptions {
+        num_dogs: opt.num_dogs.into(),
+        num_foods: opt.num_foods.into(),
+        num_ingredients: opt.num_ingredients.into(),
+        num_flavors: opt.num_flavors.into(),
+        num_dog_food_lines: opt.num_dog_food_lines.into(),
+        num_food_ingredient_lines: opt.num_food_ingredient_lines.into(),
+        num_ingredient_flavor_lines: opt.num_ingredient_flavor_lines.into(),
+    };
+    let mut rng = match opt.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let create = |filename: &str| BufWriter::new(files::create(filename).unwrap());
+    let mut dog_food_file = create(&opt.dog_food_filename);
+    let mut food_ingredient_file = create(&opt.food_ingredients_filename);
+    let mut ingredient_flavor_file = create(&opt.ingredients_flavor_filename);
+    generate_source_data(
+        &options,
+        &mut rng,
+        &mut dog_food_file,
+        &mut food_ingredient_file,
+        &mut ingredient_flavor_file,
+    )
+    .unwrap();
 
-fn write_association(
-    filename: &str,
-    items: &[String],
-    associated_items: &[String],
-    num_lines: usize,
-) {
-    let mut rng = thread_rng();
-    let mut writer = BufWriter::new(File::create(filename).unwrap());
-    for item in items {
-        for _ in 0..num_lines {
-            let line = format!("{},{}\n", item, associated_items.choose(&mut rng).unwrap());
-            writer.write_all(line.as_bytes()).unwrap();
-        }
+    for writer in [dog_food_file, food_ingredient_file, ingredient_flavor_file] {
+        writer
+            .into_inner()
+            .map_err(IntoInnerError::into_error)
+            .and_then(files::Output::finish)
+            .unwrap();
     }
-    writer.flush().unwrap();
 }
@@ -0,0 +1,400 @@
+extern crate rustyline;
+extern crate structopt;
+
+use dog_food_for_you::{
+    explain, files, get_walk, shortest_paths, CsvArgs, Mapping, Node, NodeType, Relation,
+};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::collections::HashMap;
+use structopt::StructOpt;
+
+const HELP: &str = "\
+neighbors <type> <id> [<type>]  Lists neighbors across every relation, or just to <type>
+degree <type> <id>              Counts neighbors across every relation
+walk <dog> [count] [cycles]     Samples walks from a dog
+path <type> <id> <type> <id>    Shows the shortest paths between two nodes
+explain <dog> <food>            Shows the most probable paths a walk takes from a dog to a food
+help                            Shows this
+quit                            Exits
+
+Types are dog, food, ingredient or flavor. IDs with spaces can be \"quoted\" and tab completes
+commands, types and IDs.";
+
+/// The most shortest paths `path` prints.
+const PATH_LIMIT: usize = 20;
+
+/// The most paths `explain` prints.
+const EXPLAIN_LIMIT: usize = 10;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "repl", about = "Answers questions about the loaded graph")]
+struct Opt {
+    #[structopt(long = "dog-food-file", default_value = "dog_food_lines.csv")]
+    dog_food_filename: String,
+
+    #[structopt(
+        long = "food-ingredients-file",
+        default_value = "food_ingredient_lines.csv"
+    )]
+    food_ingredients_filename: String,
+
+    #[structopt(
+        long = "ingredients-flavor-file",
+        default_value = "ingredient_flavor_lines.csv"
+    )]
+    ingredients_flavor_filename: String,
+
+    #[structopt(flatten)]
+    csv: CsvArgs,
+}
+
+/// What each argument of a command is, for tab completion.
+#[derive(Clone, Copy)]
+enum Argument {
+    Command,
+    Type,
+    /// An ID of the type given by the argument at this index.
+    Id(usize),
+    Dog,
+    Food,
+    Other,
+}
+
+fn arguments(command: &str) -> &'static [Argument] {
+    match command {
+        "neighbors" => &[Argument::Type, Argument::Id(0), Argument::Type],
+        "degree" => &[Argument::Type, Argument::Id(0)],
+        "walk" => &[Argument::Dog],
+        "path" => &[
+            Argument::Type,
+            Argument::Id(0),
+            Argument::Type,
+            Argument::Id(2),
+        ],
+        "explain" => &[Argument::Dog, Argument::Food],
+        _ => &[],
+    }
+}
+
+const COMMANDS: [&str; 7] = [
+    "neighbors",
+    "degree",
+    "walk",
+    "path",
+    "explain",
+    "help",
+    "quit",
+];
+
+struct Completions {
+    /// Every ID of each type, sorted so completions are a range.
+    ids: HashMap<NodeType, Vec<String>>,
+}
+
+impl Completer for Completions {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let mut words = split_words(line);
+        let ends_in_space = line.ends_with(char::is_whitespace) && !in_quotes(line);
+        if words.is_empty() || ends_in_space {
+            words.push((pos, String::new()));
+        }
+        let (start, prefix) = words[words.len() - 1].clone();
+
+        let argument = match words.len() {
+            1 => Argument::Command,
+            index => arguments(&words[0].1)
+                .get(index - 2)
+                .copied()
+                .unwrap_or(Argument::Other),
+        };
+        let node_type = match argument {
+            Argument::Id(type_index) => words
+                .get(type_index + 1)
+                .and_then(|(_, word)| parse_type(word)),
+            Argument::Dog => Some(NodeType::Dog),
+            Argument::Food => Some(NodeType::Food),
+            _ => None,
+        };
+
+        let candidates: Vec<String> = match (argument, node_type) {
+            (Argument::Command, _) => COMMANDS
+                .iter()
+                .filter(|command| command.starts_with(&prefix))
+                .map(|command| command.to_string())
+                .collect(),
+            (Argument::Type, _) => NodeType::ALL
+                .iter()
+                .map(|node_type| node_type.as_str())
+                .filter(|name| name.starts_with(&prefix))
+                .map(String::from)
+                .collect(),
+            (_, Some(node_type)) => {
+                let ids = &self.ids[&node_type];
+                let first = ids.partition_point(|id| id.as_str() < prefix.as_str());
+                ids[first..]
+                    .iter()
+                    .take_while(|id| id.starts_with(&prefix))
+                    .map(|id| quote(id))
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for Completions {
+    type Hint = String;
+}
+
+impl Highlighter for Completions {}
+
+impl Validator for Completions {}
+
+impl Helper for Completions {}
+
+/// Splits a line into words and where they start. Double quotes group words with spaces.
+fn split_words(line: &str) -> Vec<(usize, String)> {
+    let mut words = Vec::new();
+    let mut word: Option<(usize, String)> = None;
+    let mut quoted = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(|| (index, String::new()));
+            }
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_with(|| (index, String::new())).1.push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+fn in_quotes(line: &str) -> bool {
+    line.matches('"').count() % 2 == 1
+}
+
+fn quote(id: &str) -> String {
+    if id.contains(char::is_whitespace) {
+        format!("\"{}\"", id)
+    } else {
+        id.to_owned()
+    }
+}
+
+fn parse_type(word: &str) -> Option<NodeType> {
+    NodeType::ALL
+        .iter()
+        .copied()
+        .find(|node_type| node_type.as_str() == word || node_type.plural() == word)
+}
+
+fn main() {
+    let opt = Opt::from_args();
+
+    let csv_options = opt.csv.into_options();
+    let mapping = Mapping::with_options(
+        files::open(&opt.dog_food_filename).unwrap(),
+        files::open(&opt.food_ingredients_filename).unwrap(),
+        files::open(&opt.ingredients_flavor_filename).unwrap(),
+        &csv_options,
+    );
+
+    let mut ids = HashMap::new();
+    for &node_type in NodeType::ALL.iter() {
+        let mut type_ids: Vec<String> = mapping.nodes(node_type).cloned().collect();
+        type_ids.sort_unstable();
+        println!("Loaded {} {}", type_ids.len(), node_type.plural());
+        ids.insert(node_type, type_ids);
+    }
+    println!("Type help for a list of commands");
+
+    let mut editor = Editor::new().unwrap();
+    editor.set_helper(Some(Completions { ids }));
+    loop {
+        let line = match editor.readline("> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(error) => panic!("{}", error),
+        };
+        let words: Vec<String> = split_words(&line)
+            .into_iter()
+            .map(|(_, word)| word)
+            .collect();
+        if words.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line.as_str()).unwrap();
+
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        match run(&mapping, &words) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(message) => println!("{}", message),
+        }
+    }
+}
+
+/// Runs one command, returning whether to keep going.
+fn run(mapping: &Mapping, words: &[&str]) -> Result<bool, String> {
+    match words {
+        ["neighbors", node_type, id] => neighbors(mapping, parse(node_type)?, id, None)?,
+        ["neighbors", node_type, id, to] => {
+            neighbors(mapping, parse(node_type)?, id, Some(parse(to)?))?
+        }
+        ["degree", node_type, id] => degree(mapping, parse(node_type)?, id)?,
+        ["walk", dog] => walk(mapping, dog, "1", "4")?,
+        ["walk", dog, count] => walk(mapping, dog, count, "4")?,
+        ["walk", dog, count, cycles] => walk(mapping, dog, count, cycles)?,
+        ["path", from_type, from, to_type, to] => {
+            path(mapping, (parse(from_type)?, from), (parse(to_type)?, to))
+        }
+        ["explain", dog, food] => explain_food(mapping, dog, food)?,
+        ["help"] => println!("{}", HELP),
+        ["quit"] | ["exit"] => return Ok(false),
+        _ => return Err(String::from("Unknown command, try help")),
+    }
+    Ok(true)
+}
+
+fn parse(word: &str) -> Result<NodeType, String> {
+    parse_type(word).ok_or_else(|| format!("{} isn't a type", word))
+}
+
+fn relations_from(node_type: NodeType) -> impl Iterator<Item = Relation> {
+    Relation::ALL
+        .iter()
+        .copied()
+        .filter(move |relation| relation.from() == node_type)
+}
+
+fn check_known(mapping: &Mapping, node_type: NodeType, id: &str) -> Result<(), String> {
+    let known = relations_from(node_type).any(|relation| mapping.neighbors(relation, id).is_some());
+    if known {
+        Ok(())
+    } else {
+        Err(format!("No {} with ID {:?}", node_type, id))
+    }
+}
+
+fn neighbors(
+    mapping: &Mapping,
+    node_type: NodeType,
+    id: &str,
+    to: Option<NodeType>,
+) -> Result<(), String> {
+    check_known(mapping, node_type, id)?;
+    for relation in relations_from(node_type) {
+        if to.is_some_and(|to| to != relation.to()) {
+            continue;
+        }
+        let neighbors = mapping.neighbors(relation, id).unwrap_or(&[]);
+        println!("{} ({}):", relation.to().plural(), neighbors.len());
+        for neighbor in neighbors {
+            println!("  {}", neighbor);
+        }
+    }
+    Ok(())
+}
+
+fn degree(mapping: &Mapping, node_type: NodeType, id: &str) -> Result<(), String> {
+    check_known(mapping, node_type, id)?;
+    for relation in relations_from(node_type) {
+        let neighbors = mapping.neighbors(relation, id).unwrap_or(&[]);
+        println!("{}: {}", relation.to().plural(), neighbors.len());
+    }
+    Ok(())
+}
+
+fn walk(mapping: &Mapping, dog: &str, count: &str, cycles: &str) -> Result<(), String> {
+    check_known(mapping, NodeType::Dog, dog)?;
+    let count: usize = count
+        .parse()
+        .map_err(|_| format!("{} isn't a count", count))?;
+    let cycles: u8 = match cycles.parse() {
+        Ok(cycles) if cycles > 0 => cycles,
+        _ => return Err(format!("Cycles should be 1 to 255, not {}", cycles)),
+    };
+
+    let mut rng = rand::thread_rng();
+    for _ in 0..count {
+        println!("{}", get_walk(cycles, dog, mapping, &mut rng).join(" "));
+    }
+    Ok(())
+}
+
+fn path(mapping: &Mapping, from: Node, to: Node) {
+    let paths = shortest_paths(mapping, from, to, PATH_LIMIT);
+    if paths.is_empty() {
+        println!("No path from {} {} to {} {}", from.0, from.1, to.0, to.1);
+        return;
+    }
+
+    // Group by metapath, the sequence of types along the path.
+    let mut paths: Vec<(Vec<NodeType>, Vec<Node>)> = paths
+        .into_iter()
+        .map(|path| (path.iter().map(|(node_type, _)| *node_type).collect(), path))
+        .collect();
+    paths.sort_by(|(metapath_a, _), (metapath_b, _)| metapath_a.cmp(metapath_b));
+
+    let mut previous_metapath = None;
+    for (metapath, path) in &paths {
+        if previous_metapath != Some(metapath) {
+            let names: Vec<&str> = metapath
+                .iter()
+                .map(|node_type| node_type.as_str())
+                .collect();
+            println!("{} ({} hops):", names.join(" -> "), names.len() - 1);
+            previous_metapath = Some(metapath);
+        }
+        let ids: Vec<String> = path.iter().map(|(_, id)| quote(id)).collect();
+        println!("  {}", ids.join(" -> "));
+    }
+}
+
+fn explain_food(mapping: &Mapping, dog: &str, food: &str) -> Result<(), String> {
+    check_known(mapping, NodeType::Dog, dog)?;
+    check_known(mapping, NodeType::Food, food)?;
+    let explanation = explain(mapping, dog, food, EXPLAIN_LIMIT)
+        .ok_or_else(|| format!("No paths from {} to {}", dog, food))?;
+    if explanation.paths.is_empty() {
+        println!("No dog -> food -> ingredient -> flavor -> ingredient -> food paths");
+        return Ok(());
+    }
+
+    println!(
+        "A walk reaches {} after one cycle with probability {:.3e}, by {} paths",
+        food, explanation.probability, explanation.path_count
+    );
+    for path in &explanation.paths {
+        let (shared_type, shared) = path.shared();
+        println!(
+            "  {:.3e}  {} -> {} -> {} -> {} -> {} -> {}  (shared {} {})",
+            path.probability,
+            quote(dog),
+            quote(path.liked_food),
+            quote(path.liked_ingredient),
+            quote(path.flavor),
+            quote(path.ingredient),
+            quote(food),
+            shared_type,
+            quote(shared)
+        );
+    }
+    Ok(())
+}
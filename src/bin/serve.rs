@@ -0,0 +1,131 @@
+extern crate structopt;
+extern crate tiny_http;
+
+use dog_food_for_you::{files, CsvArgs, Embeddings, MappedGraph, Mapping, Service};
+use std::thread;
+use std::time::Instant;
+use structopt::StructOpt;
+use tiny_http::{Header as HttpHeader, Response, Server};
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "serve",
+    about = "Serves walks, recommendations and neighbors over HTTP"
+)]
+struct Opt {
+    #[structopt(long = "port", default_value = "8080")]
+    port: u16,
+
+    #[structopt(long = "threads", default_value = "4")]
+    threads: usize,
+
+    #[structopt(
+        long = "embeddings-file",
+        help = "word2vec style text vectors for the walk tokens, used for recommendations."
+    )]
+    embeddings_filename: Option<String>,
+
+    #[structopt(
+        long = "graph-file",
+        help = "A graph file written by build_graph, memory-mapped instead of reading the source files. Updates aren't allowed."
+    )]
+    graph_filename: Option<String>,
+
+    #[structopt(long = "dog-food-file", default_value = "dog_food_lines.csv")]
+    dog_food_filename: String,
+
+    #[structopt(
+        long = "food-ingredients-file",
+        default_value = "food_ingredient_lines.csv"
+    )]
+    food_ingredients_filename: String,
+
+    #[structopt(
+        long = "ingredients-flavor-file",
+        default_value = "ingredient_flavor_lines.csv"
+    )]
+    ingredients_flavor_filename: String,
+
+    #[structopt(
+        long = "dog-food-dislikes-file",
+        help = "Dog and food pairs the dog dislikes, which walks avoid and aren't recommended."
+    )]
+    dog_food_dislikes_filename: Option<String>,
+
+    #[structopt(
+        long = "dog-ingredient-exclusions-file",
+        help = "Dog and ingredient pairs for ingredients the dog can't have. Foods with them \
+                aren't recommended."
+    )]
+    dog_ingredient_exclusions_filename: Option<String>,
+
+    #[structopt(flatten)]
+    csv: CsvArgs,
+}
+
+fn main() {
+    let start = Instant::now();
+    let opt = Opt::from_args();
+
+    let csv_options = opt.csv.clone().into_options();
+    let embeddings = opt
+        .embeddings_filename
+        .as_ref()
+        .map(|filename| Embeddings::load(files::open(filename).unwrap()).unwrap());
+    let service = match &opt.graph_filename {
+        Some(filename) => {
+            if opt.dog_food_dislikes_filename.is_some()
+                || opt.dog_ingredient_exclusions_filename.is_some()
+            {
+                panic!("dislikes and exclusions can't be used with a graph file")
+            }
+            Service::mapped(MappedGraph::open(filename).unwrap(), embeddings)
+        }
+        None => {
+            let mut mapping = Mapping::with_options(
+                files::open(&opt.dog_food_filename).unwrap(),
+                files::open(&opt.food_ingredients_filename).unwrap(),
+                files::open(&opt.ingredients_flavor_filename).unwrap(),
+                &csv_options,
+            );
+            if let Some(filename) = &opt.dog_food_dislikes_filename {
+                mapping.read_dislikes(files::open(filename).unwrap(), &csv_options);
+            }
+            if let Some(filename) = &opt.dog_ingredient_exclusions_filename {
+                mapping.read_exclusions(files::open(filename).unwrap(), &csv_options);
+            }
+            Service::new(mapping, embeddings)
+        }
+    };
+    let service = service.with_csv_options(csv_options);
+
+    let server = Server::http(("127.0.0.1", opt.port)).unwrap();
+    eprintln!(
+        "Loaded in {}ms, listening on http://{}",
+        start.elapsed().as_millis(),
+        server.server_addr()
+    );
+
+    let content_type: HttpHeader = "Content-Type: application/json".parse().unwrap();
+    thread::scope(|scope| {
+        for _ in 0..opt.threads.max(1) {
+            scope.spawn(|| {
+                for mut request in server.incoming_requests() {
+                    let mut body = Vec::new();
+                    if let Err(error) = request.as_reader().read_to_end(&mut body) {
+                        eprintln!("Couldn't read request: {}", error);
+                        continue;
+                    }
+                    let response =
+                        service.handle_with_body(request.method().as_str(), request.url(), &body);
+                    let http_response = Response::from_string(response.body.to_string())
+                        .with_status_code(response.status)
+                        .with_header(content_type.clone());
+                    if let Err(error) = request.respond(http_response) {
+                        eprintln!("Couldn't respond: {}", error);
+                    }
+                }
+            });
+        }
+    });
+}
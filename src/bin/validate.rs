@@ -0,0 +1,82 @@
+extern crate structopt;
+
+use dog_food_for_you::{files, CsvArgs, InputFile, Reason};
+use std::collections::HashMap;
+use std::process;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "validate",
+    about = "Reports rows of the source files that are dropped or look wrong"
+)]
+struct Opt {
+    #[structopt(long = "dog-food-file", default_value = "dog_food_lines.csv")]
+    dog_food_filename: String,
+
+    #[structopt(
+        long = "food-ingredients-file",
+        default_value = "food_ingredient_lines.csv"
+    )]
+    food_ingredients_filename: String,
+
+    #[structopt(
+        long = "ingredients-flavor-file",
+        default_value = "ingredient_flavor_lines.csv"
+    )]
+    ingredients_flavor_filename: String,
+
+    #[structopt(flatten)]
+    csv: CsvArgs,
+}
+
+fn main() {
+    let opt = Opt::from_args();
+
+    let problems = dog_food_for_you::validate_with_options(
+        files::open(&opt.dog_food_filename).unwrap(),
+        files::open(&opt.food_ingredients_filename).unwrap(),
+        files::open(&opt.ingredients_flavor_filename).unwrap(),
+        &opt.csv.into_options(),
+    );
+
+    let mut counts: HashMap<Reason, usize> = HashMap::new();
+    for problem in &problems {
+        let filename = match problem.input {
+            InputFile::DogFood => &opt.dog_food_filename,
+            InputFile::FoodIngredient => &opt.food_ingredients_filename,
+            InputFile::IngredientFlavor => &opt.ingredients_flavor_filename,
+            InputFile::DogFoodDislikes | InputFile::DogIngredientExclusions => {
+                unreachable!("validate only reads the three source files")
+            }
+        };
+        println!(
+            "{}:{}: {} ({}): {}",
+            filename,
+            problem.line_number,
+            problem.reason.code(),
+            problem.reason,
+            problem.line
+        );
+        *counts.entry(problem.reason).or_default() += 1;
+    }
+
+    if problems.is_empty() {
+        eprintln!("No problems found");
+        return;
+    }
+
+    let mut counts: Vec<(Reason, usize)> = counts.into_iter().collect();
+    counts.sort_by_key(|(reason, _)| reason.code());
+    for (reason, count) in counts {
+        let action = if reason.is_fatal() {
+            "fatal"
+        } else if reason.is_dropped() {
+            "dropped"
+        } else {
+            "flagged"
+        };
+        eprintln!("{} {} ({})", count, action, reason.code());
+    }
+    process::exit(1);
+}
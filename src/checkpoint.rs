@@ -0,0 +1,257 @@
+use super::get_line::WalkStats;
+use super::mapping::NodeType;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+
+/// How far one output shard had got at a checkpoint.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ShardState {
+    pub filename: String,
+    pub bytes: u64,
+    pub lines: u64,
+    pub crc32: u32,
+}
+
+/// What's been fully written so far. Start nodes are walked in order of type and then ID, so
+/// the first `nodes_done` of them are done. With a seed, each node's RNG is seeded from it and
+/// the node's type and ID, so the seed is all the RNG state there is to record.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Checkpoint {
+    pub seed: Option<u64>,
+    pub lines_per_dog: u8,
+    pub walks_per_line: u8,
+    pub format: String,
+    /// Like `dog,food`.
+    pub start_types: String,
+    pub walks_per_node: String,
+    /// Like `non-backtracking`.
+    pub mode: String,
+    pub nodes_done: usize,
+    /// The type and ID of the last node done, to catch source files changing between runs.
+    pub last_node: Option<(NodeType, String)>,
+    /// Tokens written for the first `nodes_done` nodes, which the shards can't tell us.
+    pub tokens: u64,
+    /// Also for the first `nodes_done` nodes, so the report covers the whole output.
+    pub stats: WalkStats,
+    pub shards: Vec<ShardState>,
+}
+
+impl Checkpoint {
+    /// Writes to a temporary file first and renames it over `filename`, so a crash while
+    /// saving leaves the previous checkpoint.
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        let shards: Vec<Value> = self
+            .shards
+            .iter()
+            .map(|shard| {
+                json!({
+                    "file": shard.filename,
+                    "bytes": shard.bytes,
+                    "lines": shard.lines,
+                    "crc32": shard.crc32,
+                })
+            })
+            .collect();
+        let json = json!({
+            "seed": self.seed,
+            "lines_per_dog": self.lines_per_dog,
+            "walks_per_line": self.walks_per_line,
+            "format": self.format,
+            "start_types": self.start_types,
+            "walks_per_node": self.walks_per_node,
+            "mode": self.mode,
+            "nodes_done": self.nodes_done,
+            "last_node": self.last_node.as_ref().map(|(node_type, id)| {
+                json!({ "type": node_type.as_str(), "id": id })
+            }),
+            "tokens": self.tokens,
+            "dog_food_steps": self.stats.dog_food_steps,
+            "dislike_hits": self.stats.dislike_hits,
+            "exclusion_hits": self.stats.exclusion_hits,
+            "shards": shards,
+        });
+
+        let temporary = format!("{}.tmp", filename);
+        fs::write(&temporary, json.to_string() + "\n")?;
+        fs::rename(&temporary, filename)
+    }
+
+    pub fn load(filename: &str) -> io::Result<Self> {
+        let json: Value = serde_json::from_str(&fs::read_to_string(filename)?)
+            .map_err(|error| invalid(format!("{}: {}", filename, error)))?;
+        Self::from_json(&json).ok_or_else(|| invalid(format!("{} isn't a checkpoint", filename)))
+    }
+
+    fn from_json(json: &Value) -> Option<Self> {
+        let shards = json["shards"]
+            .as_array()?
+            .iter()
+            .map(|shard| {
+                Some(ShardState {
+                    filename: shard["file"].as_str()?.to_owned(),
+                    bytes: shard["bytes"].as_u64()?,
+                    lines: shard["lines"].as_u64()?,
+                    crc32: shard["crc32"].as_u64()? as u32,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Checkpoint {
+            seed: match &json["seed"] {
+                Value::Null => None,
+                seed => Some(seed.as_u64()?),
+            },
+            lines_per_dog: json["lines_per_dog"].as_u64()? as u8,
+            walks_per_line: json["walks_per_line"].as_u64()? as u8,
+            format: json["format"].as_str()?.to_owned(),
+            start_types: json["start_types"].as_str()?.to_owned(),
+            walks_per_node: json["walks_per_node"].as_str()?.to_owned(),
+            mode: json["mode"].as_str()?.to_owned(),
+            nodes_done: json["nodes_done"].as_u64()? as usize,
+            last_node: match &json["last_node"] {
+                Value::Null => None,
+                node => Some((
+                    node["type"].as_str()?.parse().ok()?,
+                    node["id"].as_str()?.to_owned(),
+                )),
+            },
+            tokens: json["tokens"].as_u64()?,
+            stats: WalkStats {
+                dog_food_steps: json["dog_food_steps"].as_u64()?,
+                dislike_hits: json["dislike_hits"].as_f64()?,
+                exclusion_hits: json["exclusion_hits"].as_u64()?,
+            },
+            shards,
+        })
+    }
+
+    /// Errors if `self` was saved by a run with different settings, since resuming it would
+    /// mix two kinds of output.
+    pub fn check_matches(&self, expected: &Checkpoint) -> io::Result<()> {
+        let setting = |name: &str, saved: String, expected: String| {
+            if saved == expected {
+                Ok(())
+            } else {
+                Err(invalid(format!(
+                    "the checkpoint was saved with {} {} but this run has {}",
+                    name, saved, expected
+                )))
+            }
+        };
+        setting(
+            "seed",
+            format!("{:?}", self.seed),
+            format!("{:?}", expected.seed),
+        )?;
+        setting(
+            "lines per dog",
+            self.lines_per_dog.to_string(),
+            expected.lines_per_dog.to_string(),
+        )?;
+        setting(
+            "walks per line",
+            self.walks_per_line.to_string(),
+            expected.walks_per_line.to_string(),
+        )?;
+        setting("format", self.format.clone(), expected.format.clone())?;
+        setting(
+            "start types",
+            self.start_types.clone(),
+            expected.start_types.clone(),
+        )?;
+        setting(
+            "walks per node",
+            self.walks_per_node.clone(),
+            expected.walks_per_node.clone(),
+        )?;
+        setting("walk mode", self.mode.clone(), expected.mode.clone())?;
+        let filenames = |checkpoint: &Checkpoint| {
+            checkpoint
+                .shards
+                .iter()
+                .map(|shard| shard.filename.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        setting("output files", filenames(self), filenames(expected))
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn checkpoint() -> Checkpoint {
+        Checkpoint {
+            seed: Some(7),
+            lines_per_dog: 2,
+            walks_per_line: 3,
+            format: String::from("text"),
+            start_types: String::from("dog"),
+            walks_per_node: String::from("constant"),
+            mode: String::from("simple"),
+            nodes_done: 1,
+            last_node: Some((NodeType::Food, String::from("17"))),
+            tokens: 14,
+            stats: WalkStats {
+                dog_food_steps: 4,
+                dislike_hits: 1.5,
+                exclusion_hits: 1,
+            },
+            shards: vec![ShardState {
+                filename: String::from("output.txt"),
+                bytes: 120,
+                lines: 2,
+                crc32: 0xdead_beef,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let filename = env::temp_dir()
+            .join("dog_food_for_you_checkpoint.json")
+            .to_str()
+            .unwrap()
+            .to_owned();
+        checkpoint().save(&filename).unwrap();
+        assert_eq!(Checkpoint::load(&filename).unwrap(), checkpoint());
+
+        let unseeded = Checkpoint {
+            seed: None,
+            last_node: None,
+            ..checkpoint()
+        };
+        unseeded.save(&filename).unwrap();
+        assert_eq!(Checkpoint::load(&filename).unwrap(), unseeded);
+    }
+
+    #[test]
+    fn test_check_matches() {
+        assert!(checkpoint().check_matches(&checkpoint()).is_ok());
+        let other = Checkpoint {
+            seed: Some(8),
+            ..checkpoint()
+        };
+        let error = checkpoint().check_matches(&other).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the checkpoint was saved with seed Some(7) but this run has Some(8)"
+        );
+
+        let other = Checkpoint {
+            mode: String::from("non-backtracking"),
+            ..checkpoint()
+        };
+        let error = checkpoint().check_matches(&other).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the checkpoint was saved with walk mode simple but this run has non-backtracking"
+        );
+    }
+}
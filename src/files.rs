@@ -0,0 +1,178 @@
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+/// How a file is compressed, going by its extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("gz") => Compression::Gzip,
+            Some("zst") | Some("zstd") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// The path that stands for stdin when reading and stdout when writing.
+pub const STANDARD_STREAM: &str = "-";
+
+fn is_standard_stream<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref() == Path::new(STANDARD_STREAM)
+}
+
+/// Opens `path` for reading, decompressing it as it's read if its extension says it's
+/// compressed. `-` reads from stdin. Named pipes work too since files are only read once, from
+/// start to finish.
+pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn Read>> {
+    if is_standard_stream(&path) {
+        return Ok(Box::new(io::stdin()));
+    }
+
+    let file = File::open(&path)?;
+    let reader: Box<dyn Read> = match Compression::from_path(&path) {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(BufReader::new(file))),
+        Compression::Zstd => Box::new(zstd::Decoder::new(file)?),
+    };
+    Ok(reader)
+}
+
+/// Creates `path` for writing, compressing what's written if its extension asks for it.
+/// `-` writes to stdout. `Output::finish` must be called once everything is written.
+pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Output> {
+    if is_standard_stream(&path) {
+        return Ok(Output::Stdout(io::stdout()));
+    }
+
+    let file = File::create(&path)?;
+    let output = match Compression::from_path(&path) {
+        Compression::None => Output::Plain(file),
+        Compression::Gzip => Output::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+        Compression::Zstd => Output::Zstd(zstd::Encoder::new(file, 0)?),
+    };
+    Ok(output)
+}
+
+/// A file being written, possibly through a compressor.
+pub enum Output {
+    Stdout(io::Stdout),
+    Plain(File),
+    Gzip(GzEncoder<File>),
+    Zstd(zstd::Encoder<'static, File>),
+}
+
+impl Output {
+    /// Writes out anything the compressor is holding on to, along with its trailer.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Output::Stdout(mut stdout) => stdout.flush(),
+            Output::Plain(mut file) => file.flush(),
+            Output::Gzip(encoder) => encoder.finish()?.flush(),
+            Output::Zstd(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout(stdout) => stdout.write(buf),
+            Output::Plain(file) => file.write(buf),
+            Output::Gzip(encoder) => encoder.write(buf),
+            Output::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout(stdout) => stdout.flush(),
+            Output::Plain(file) => file.flush(),
+            Output::Gzip(encoder) => encoder.flush(),
+            Output::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn round_trip(filename: &str) -> (String, Vec<u8>) {
+        let path = env::temp_dir().join(format!("dog_food_for_you_{}", filename));
+        let mut output = create(&path).unwrap();
+        output.write_all(b"Sparky,burger\nMax,taco\n").unwrap();
+        output.finish().unwrap();
+
+        let mut contents = String::new();
+        open(&path).unwrap().read_to_string(&mut contents).unwrap();
+        let raw = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        (contents, raw)
+    }
+
+    #[test]
+    fn test_compression_from_path() {
+        assert_eq!(Compression::from_path("output.txt"), Compression::None);
+        assert_eq!(Compression::from_path("output.txt.gz"), Compression::Gzip);
+        assert_eq!(Compression::from_path("output.txt.zst"), Compression::Zstd);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (contents, raw) = round_trip("round_trip.csv");
+        assert_eq!(contents, "Sparky,burger\nMax,taco\n");
+        assert_eq!(raw, b"Sparky,burger\nMax,taco\n");
+
+        let (contents, raw) = round_trip("round_trip.csv.gz");
+        assert_eq!(contents, "Sparky,burger\nMax,taco\n");
+        assert_eq!(&raw[..2], &[0x1f, 0x8b]);
+
+        let (contents, raw) = round_trip("round_trip.csv.zst");
+        assert_eq!(contents, "Sparky,burger\nMax,taco\n");
+        assert_eq!(&raw[..4], &[0x28, 0xb5, 0x2f, 0xfd]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_named_pipe() {
+        use std::process::Command;
+        use std::thread;
+
+        let path = env::temp_dir().join("dog_food_for_you_named_pipe.csv");
+        let _ = fs::remove_file(&path);
+        assert!(Command::new("mkfifo")
+            .arg(&path)
+            .status()
+            .unwrap()
+            .success());
+
+        let writer_path = path.clone();
+        let writer = thread::spawn(move || {
+            let mut output = create(&writer_path).unwrap();
+            output.write_all(b"Sparky,burger\n").unwrap();
+            output.finish().unwrap();
+        });
+        let mut contents = String::new();
+        open(&path).unwrap().read_to_string(&mut contents).unwrap();
+        writer.join().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(contents, "Sparky,burger\n");
+    }
+}
@@ -0,0 +1,33 @@
+use super::mapping::Mapping;
+
+/// The small burger, pizza and taco graph most tests use: Sparky likes burger and pizza, and
+/// Max likes burger and taco.
+pub(crate) fn mapping() -> Mapping {
+    mapping_with("")
+}
+
+/// `mapping()` with more `dog,food` rows, like `"Sir Barks,taco"`.
+pub(crate) fn mapping_with(dog_food: &str) -> Mapping {
+    Mapping::new(
+        format!(
+            "Sparky,burger\nSparky,pizza\nMax,burger\nMax,taco\n{}",
+            dog_food
+        )
+        .as_bytes(),
+        "burger,cheese\nburger,tomato\npizza,cheese\ntaco,beef".as_bytes(),
+        "cheese,salty\ntomato,salty\ntomato,savory\nbeef,savory".as_bytes(),
+    )
+}
+
+/// Nodes of several degrees, and Sparky likes taco twice.
+pub(crate) fn weighted_mapping() -> Mapping {
+    Mapping::new(
+        "Sparky,burger\nSparky,pizza\nSparky,taco\nSparky,taco\nMax,burger\nMax,taco\nRex,pizza"
+            .as_bytes(),
+        "burger,beef\nburger,cheese\nburger,tomato\npizza,cheese\npizza,tomato\ntaco,beef\n\
+         taco,cheese\ntaco,lettuce"
+            .as_bytes(),
+        "beef,savory\ncheese,salty\ncheese,savory\ntomato,sweet\ntomato,savory\nlettuce,fresh"
+            .as_bytes(),
+    )
+}
@@ -0,0 +1,250 @@
+use super::files;
+use super::mapping::{metapath_from, Mapping, NodeType};
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// How each walk is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// One line of space separated IDs per walk.
+    Text,
+    /// One JSON object per line with the origin dog, the walk's index for that dog and its
+    /// tokens.
+    Jsonl,
+    /// Every token as a little-endian `u32`, with no separators since every walk has the same
+    /// number of tokens. The tokens are listed in a separate vocabulary file.
+    Ids,
+}
+
+impl Format {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Format::Text => "text",
+            Format::Jsonl => "jsonl",
+            Format::Ids => "ids",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "jsonl" => Ok(Format::Jsonl),
+            "ids" => Ok(Format::Ids),
+            _ => Err(format!("expected text, jsonl or ids but got {:?}", s)),
+        }
+    }
+}
+
+/// Numbers every node in a `Mapping`. IDs are assigned by type and then by name so the same
+/// graph always gets the same vocabulary. The same name can appear once for each type.
+pub(crate) struct Vocabulary<'a> {
+    ids: HashMap<(NodeType, &'a str), u32>,
+    tokens: Vec<(NodeType, &'a str)>,
+}
+
+impl<'a> Vocabulary<'a> {
+    pub fn new(mapping: &'a Mapping) -> Self {
+        let mut tokens = Vec::new();
+        for &node_type in NodeType::ALL.iter() {
+            let mut names: Vec<&str> = mapping.nodes(node_type).map(String::as_str).collect();
+            names.sort_unstable();
+            tokens.extend(names.into_iter().map(|name| (node_type, name)));
+        }
+        let ids = tokens
+            .iter()
+            .enumerate()
+            .map(|(id, &token)| (token, id as u32))
+            .collect();
+
+        Vocabulary { ids, tokens }
+    }
+
+    pub fn id(&self, node_type: NodeType, name: &str) -> u32 {
+        self.ids[&(node_type, name)]
+    }
+
+    /// Every node, in ID order.
+    pub fn tokens(&self) -> &[(NodeType, &'a str)] {
+        &self.tokens
+    }
+
+    /// Writes `id\ttype\tname` lines, in ID order. Backslashes, tabs and line breaks in names
+    /// are written as `\\`, `\t`, `\n` and `\r`, so every node stays on one line.
+    pub fn write(&self, filename: &str) -> io::Result<()> {
+        let mut file = io::BufWriter::new(files::create(filename)?);
+        writeln!(file, "id\ttype\tname")?;
+        for (id, (node_type, name)) in self.tokens.iter().enumerate() {
+            writeln!(file, "{}\t{}\t{}", id, node_type, escape(name))?;
+        }
+        file.into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .finish()
+    }
+}
+
+fn escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Appends `walk`, which starts from a node of type `start_type`, to `buffer` in the given
+/// format. `index` counts the walks from `walk[0]`.
+pub(crate) fn encode(
+    format: Format,
+    start_type: NodeType,
+    walk: &[&str],
+    index: usize,
+    vocabulary: Option<&Vocabulary>,
+    buffer: &mut Vec<u8>,
+) {
+    match format {
+        Format::Text => {
+            buffer.extend_from_slice(walk.join(" ").as_bytes());
+            buffer.push(b'\n');
+        }
+        Format::Jsonl => {
+            let mut walk_json = json!({ "walk": index, "tokens": walk });
+            walk_json[start_type.as_str()] = json!(walk[0]);
+            serde_json::to_writer(&mut *buffer, &walk_json).unwrap();
+            buffer.push(b'\n');
+        }
+        Format::Ids => {
+            let vocabulary = vocabulary.expect("the ids format needs a vocabulary");
+            let metapath = metapath_from(start_type);
+            for (position, name) in walk.iter().enumerate() {
+                let id = vocabulary.id(metapath[position % metapath.len()], name);
+                buffer.extend_from_slice(&id.to_le_bytes());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn mapping() -> Mapping {
+        Mapping::new(
+            "Sparky,burger\nMax,burger".as_bytes(),
+            "burger,cheese".as_bytes(),
+            "cheese,salty".as_bytes(),
+        )
+    }
+
+    #[test]
+    fn test_encode_text() {
+        let mut buffer = Vec::new();
+        let walk = [
+            "Sparky", "burger", "cheese", "salty", "cheese", "burger", "Max",
+        ];
+        encode(Format::Text, NodeType::Dog, &walk, 3, None, &mut buffer);
+
+        assert_eq!(buffer, b"Sparky burger cheese salty cheese burger Max\n");
+    }
+
+    #[test]
+    fn test_encode_jsonl() {
+        let mut buffer = Vec::new();
+        let walk = [
+            "Sparky", "burger", "cheese", "salty", "cheese", "burger", "Max",
+        ];
+        encode(Format::Jsonl, NodeType::Dog, &walk, 3, None, &mut buffer);
+
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "{\"dog\":\"Sparky\",\"tokens\":[\"Sparky\",\"burger\",\"cheese\",\"salty\",\
+             \"cheese\",\"burger\",\"Max\"],\"walk\":3}\n"
+        );
+    }
+
+    #[test]
+    fn test_encode_ids() {
+        let mapping = mapping();
+        let vocabulary = Vocabulary::new(&mapping);
+        let mut buffer = Vec::new();
+        let walk = [
+            "Sparky", "burger", "cheese", "salty", "cheese", "burger", "Max",
+        ];
+        encode(
+            Format::Ids,
+            NodeType::Dog,
+            &walk,
+            0,
+            Some(&vocabulary),
+            &mut buffer,
+        );
+
+        // Max, Sparky, burger, cheese, salty
+        let expected: Vec<u8> = [1u32, 2, 3, 4, 3, 2, 0]
+            .iter()
+            .flat_map(|id| id.to_le_bytes().to_vec())
+            .collect();
+        assert_eq!(buffer, expected);
+    }
+
+    #[test]
+    fn test_encode_ids_from_food() {
+        let mapping = mapping();
+        let vocabulary = Vocabulary::new(&mapping);
+        let mut buffer = Vec::new();
+        let walk = [
+            "burger", "cheese", "salty", "cheese", "burger", "Sparky", "burger",
+        ];
+        encode(
+            Format::Ids,
+            NodeType::Food,
+            &walk,
+            0,
+            Some(&vocabulary),
+            &mut buffer,
+        );
+
+        let expected: Vec<u8> = [2u32, 3, 4, 3, 2, 1, 2]
+            .iter()
+            .flat_map(|id| id.to_le_bytes().to_vec())
+            .collect();
+        assert_eq!(buffer, expected);
+    }
+
+    #[test]
+    fn test_write_vocabulary_escapes_names() {
+        let mapping = Mapping::new(
+            "\"Sparky\tthe\\dog\",burger\n\"Max\r\nII\",burger\n".as_bytes(),
+            "burger,cheese".as_bytes(),
+            "cheese,salty".as_bytes(),
+        );
+        let filename = env::temp_dir()
+            .join("dog_food_for_you_vocabulary.tsv")
+            .to_str()
+            .unwrap()
+            .to_owned();
+        Vocabulary::new(&mapping).write(&filename).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&filename).unwrap(),
+            "id\ttype\tname\n\
+             0\tdog\tMax\\r\\nII\n\
+             1\tdog\tSparky\\tthe\\\\dog\n\
+             2\tfood\tburger\n\
+             3\tingredient\tcheese\n\
+             4\tflavor\tsalty\n"
+        );
+    }
+}
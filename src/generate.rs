@@ -0,0 +1,155 @@
+use rand::distributions::Alphanumeric;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::io::{self, Write};
+
+/// How much random source data `generate_source_data` makes.
+#[derive(Clone, Debug)]
+pub struct SourceDataOptions {
+    pub num_dogs: usize,
+    pub num_foods: usize,
+    pub num_ingredients: usize,
+    pub num_flavors: usize,
+    /// Lines per dog in the dog_food file.
+    pub num_dog_food_lines: usize,
+    /// Lines per food in the food_ingredient file.
+    pub num_food_ingredient_lines: usize,
+    /// Lines per ingredient in the ingredient_flavor file.
+    pub num_ingredient_flavor_lines: usize,
+}
+
+impl Default for SourceDataOptions {
+    fn default() -> Self {
+        SourceDataOptions {
+            num_dogs: 5000,
+            num_foods: 5000,
+            num_ingredients: 500,
+            num_flavors: 10,
+            num_dog_food_lines: 100,
+            num_food_ingredient_lines: 50,
+            num_ingredient_flavor_lines: 10,
+        }
+    }
+}
+
+/// Writes the three source files with random IDs and associations. The same `rng` seed always
+/// makes the same files, so benchmarks can be compared between commits.
+///
+/// # Panics
+///
+/// If there are no dogs, foods, ingredients or flavors.
+pub fn generate_source_data<R, W>(
+    options: &SourceDataOptions,
+    rng: &mut R,
+    dog_food_file: &mut W,
+    food_ingredient_file: &mut W,
+    ingredient_flavor_file: &mut W,
+) -> io::Result<()>
+where
+    R: Rng,
+    W: Write,
+{
+    assert!(options.num_dogs > 0, "num_dogs must be at least 1");
+    assert!(options.num_foods > 0, "num_foods must be at least 1");
+    assert!(
+        options.num_ingredients > 0,
+        "num_ingredients must be at least 1"
+    );
+    assert!(options.num_flavors > 0, "num_flavors must be at least 1");
+
+    let dogs = ids("dog", options.num_dogs, rng);
+    let foods = ids("food", options.num_foods, rng);
+    let ingredients = ids("ingredient", options.num_ingredients, rng);
+    let flavors = ids("flavor", options.num_flavors, rng);
+
+    write_association(
+        dog_food_file,
+        &dogs,
+        &foods,
+        options.num_dog_food_lines,
+        rng,
+    )?;
+    write_association(
+        food_ingredient_file,
+        &foods,
+        &ingredients,
+        options.num_food_ingredient_lines,
+        rng,
+    )?;
+    write_association(
+        ingredient_flavor_file,
+        &ingredients,
+        &flavors,
+        options.num_ingredient_flavor_lines,
+        rng,
+    )
+}
+
+fn ids<R: Rng>(prefix: &str, count: usize, rng: &mut R) -> Vec<String> {
+    (0..count)
+        .map(|_| {
+            let id: String = (&mut *rng).sample_iter(&Alphanumeric).take(10).collect();
+            format!("{}-{}", prefix, id)
+        })
+        .collect()
+}
+
+fn write_association<R: Rng, W: Write>(
+    writer: &mut W,
+    items: &[String],
+    associated_items: &[String],
+    num_lines: usize,
+    rng: &mut R,
+) -> io::Result<()> {
+    for item in items {
+        for _ in 0..num_lines {
+            let line = format!("{},{}\n", item, associated_items.choose(rng).unwrap());
+            writer.write_all(line.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn generate(seed: u64) -> Vec<Vec<u8>> {
+        let options = SourceDataOptions {
+            num_dogs: 3,
+            num_foods: 4,
+            num_ingredients: 5,
+            num_flavors: 2,
+            num_dog_food_lines: 2,
+            num_food_ingredient_lines: 3,
+            num_ingredient_flavor_lines: 1,
+        };
+        let (mut dog_food, mut food_ingredient, mut ingredient_flavor) =
+            (Vec::new(), Vec::new(), Vec::new());
+        generate_source_data(
+            &options,
+            &mut StdRng::seed_from_u64(seed),
+            &mut dog_food,
+            &mut food_ingredient,
+            &mut ingredient_flavor,
+        )
+        .unwrap();
+        vec![dog_food, food_ingredient, ingredient_flavor]
+    }
+
+    #[test]
+    fn test_generate_source_data() {
+        let files = generate(1);
+        let line_counts: Vec<usize> = files
+            .iter()
+            .map(|file| file.iter().filter(|&&byte| byte == b'\n').count())
+            .collect();
+        assert_eq!(line_counts, vec![6, 12, 5]);
+        assert!(files[0].starts_with(b"dog-"));
+
+        assert_eq!(generate(1), files);
+        assert_ne!(generate(2), files);
+    }
+}
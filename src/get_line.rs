@@ -1,30 +1,533 @@
-use super::mapping::Mapping;
-use rand::Rng;
+use super::graph::Graph;
+use super::mapping::{metapath_from, NodeType, Relation};
+use super::output::node_hash;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+use std::str::FromStr;
 
-pub fn get_line<R: Rng>(walks_per_line: u8, dog: &str, maps: &Mapping, mut rng: R) -> String {
-    let mut line = Vec::with_capacity(walks_per_line as usize * 6 + 1);
-    line.push(dog);
+/// How many walks `write_file` takes from each start node, going by `lines_per_dog`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WalksPerNode {
+    /// `lines_per_dog` from every node.
+    Constant,
+    /// In proportion to the node's degree, so a node of average degree for its type gets
+    /// `lines_per_dog`. Every node gets at least one.
+    Degree,
+    /// Like `Degree`, but in proportion to `ln(1 + degree)` so hubs don't take over.
+    LogDegree,
+    /// Until `ADAPTIVE_STABLE_WALKS` walks in a row visit no node the node's earlier walks
+    /// hadn't, or `lines_per_dog` walks.
+    Adaptive,
+}
+
+/// Whether a walk can step straight back to where it just was.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WalkMode {
+    /// Every neighbor is equally likely.
+    Simple,
+    /// Never steps to the last node of the same type, like the dog a walk just came from,
+    /// unless it's the only neighbor. This avoids "dog food … food dog" segments that only
+    /// repeat what the walk already saw.
+    NonBacktracking,
+}
+
+impl WalkMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WalkMode::Simple => "simple",
+            WalkMode::NonBacktracking => "non-backtracking",
+        }
+    }
+}
+
+impl FromStr for WalkMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "simple" => Ok(WalkMode::Simple),
+            "non-backtracking" => Ok(WalkMode::NonBacktracking),
+            _ => Err(format!(
+                "expected simple or non-backtracking but got {:?}",
+                s
+            )),
+        }
+    }
+}
+
+/// How many walks in a row have to find nothing new before `WalksPerNode::Adaptive` stops.
+pub const ADAPTIVE_STABLE_WALKS: usize = 3;
+
+impl WalksPerNode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WalksPerNode::Constant => "constant",
+            WalksPerNode::Degree => "degree",
+            WalksPerNode::LogDegree => "log-degree",
+            WalksPerNode::Adaptive => "adaptive",
+        }
+    }
+
+    /// What a node's walks are in proportion to.
+    pub(crate) fn weight(self, degree: usize) -> f64 {
+        match self {
+            WalksPerNode::Degree => degree as f64,
+            WalksPerNode::LogDegree => (degree as f64).ln_1p(),
+            WalksPerNode::Constant | WalksPerNode::Adaptive => 1.0,
+        }
+    }
+
+    /// The walks to take from a node of weight `weight` when the mean for its type is
+    /// `mean_weight`, or `None` for `Adaptive`, which decides as it goes.
+    pub(crate) fn target(self, lines_per_dog: u8, weight: f64, mean_weight: f64) -> Option<usize> {
+        match self {
+            WalksPerNode::Constant => Some(lines_per_dog.into()),
+            WalksPerNode::Degree | WalksPerNode::LogDegree => {
+                let lines = f64::from(lines_per_dog) * weight / mean_weight;
+                Some((lines.round() as usize).max(1))
+            }
+            WalksPerNode::Adaptive => None,
+        }
+    }
+}
+
+impl FromStr for WalksPerNode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "constant" => Ok(WalksPerNode::Constant),
+            "degree" => Ok(WalksPerNode::Degree),
+            "log-degree" => Ok(WalksPerNode::LogDegree),
+            "adaptive" => Ok(WalksPerNode::Adaptive),
+            _ => Err(format!(
+                "expected constant, degree, log-degree or adaptive but got {:?}",
+                s
+            )),
+        }
+    }
+}
+
+/// The nodes one start node's walks have visited, for `WalksPerNode::Adaptive`.
+#[derive(Default)]
+pub(crate) struct Coverage<'a> {
+    visited: HashSet<(NodeType, &'a str)>,
+    stable_walks: usize,
+}
+
+impl<'a> Coverage<'a> {
+    /// Adds a walk from a node of type `start_type`.
+    pub fn add(&mut self, start_type: NodeType, walk: &[&'a str]) {
+        let metapath = metapath_from(start_type);
+        let mut found_new = false;
+        for (position, &node) in walk.iter().enumerate() {
+            found_new |= self
+                .visited
+                .insert((metapath[position % metapath.len()], node));
+        }
+        self.stable_walks = if found_new { 0 } else { self.stable_walks + 1 };
+    }
 
-    let mut dog = dog;
+    pub fn is_stable(&self) -> bool {
+        self.stable_walks >= ADAPTIVE_STABLE_WALKS
+    }
+}
+
+/// How many neighbors `node` has in every relation from its type.
+pub(crate) fn degree<G: Graph>(graph: &G, node_type: NodeType, node: &str) -> usize {
+    Relation::ALL
+        .iter()
+        .filter(|relation| relation.from() == node_type)
+        .filter_map(|&relation| graph.neighbor_list(relation, node))
+        .map(|neighbors| neighbors.len())
+        .sum()
+}
+
+/// How many walks `write_file` takes from each dog, and how they're sampled.
+#[derive(Clone, Debug)]
+pub struct WalkOptions {
+    /// Lines per start node, which are dogs unless `start_types` says otherwise. How it's used
+    /// depends on `walks_per_node`.
+    pub lines_per_dog: u8,
+    pub walks_per_line: u8,
+    /// Makes the same walks every time, for the same graph and version of this crate. Each start
+    /// node gets its own RNG seeded from this and its ID, along with its type if it isn't a dog,
+    /// so its walks don't depend on which nodes came before it.
+    pub seed: Option<u64>,
+    /// Only walk from these dogs instead of every dog. Can't leave out any dogs if
+    /// `start_types` has other types.
+    pub dogs: Option<Vec<String>>,
+    /// The types of node to start walks from. Walks from other types follow `METAPATH` rotated
+    /// to begin at their type, so rarer foods, ingredients and flavors can get walks of their own.
+    pub start_types: Vec<NodeType>,
+    pub walks_per_node: WalksPerNode,
+    pub mode: WalkMode,
+}
+
+impl WalkOptions {
+    pub fn new(lines_per_dog: u8, walks_per_line: u8) -> Self {
+        WalkOptions {
+            lines_per_dog,
+            walks_per_line,
+            seed: None,
+            dogs: None,
+            start_types: vec![NodeType::Dog],
+            walks_per_node: WalksPerNode::Constant,
+            mode: WalkMode::Simple,
+        }
+    }
+
+    /// The RNG for the walks from `node`, a node of type `node_type`.
+    pub(crate) fn rng_for(&self, node_type: NodeType, node: &str) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed ^ node_hash(node_type, node)),
+            None => StdRng::from_rng(rand::thread_rng()).unwrap(),
+        }
+    }
+}
+
+/// `get_walk` as a line of space separated IDs.
+///
+/// # Panics
+///
+/// If `dog` isn't a dog in `maps`.
+pub fn get_line<G: Graph, R: Rng>(walks_per_line: u8, dog: &str, maps: &G, rng: R) -> String {
+    get_walk(walks_per_line, dog, maps, rng).join(" ") + "\n"
+}
+
+/// The tokens of one line, starting and ending with a dog. The type of each token follows
+/// `METAPATH`.
+///
+/// # Panics
+///
+/// If `dog` isn't a dog in `maps`.
+pub fn get_walk<'a, G: Graph, R: Rng>(
+    walks_per_line: u8,
+    dog: &'a str,
+    maps: &'a G,
+    rng: R,
+) -> Vec<&'a str> {
+    get_walk_from(
+        walks_per_line,
+        NodeType::Dog,
+        dog,
+        WalkMode::Simple,
+        maps,
+        rng,
+        &mut WalkStats::default(),
+    )
+}
+
+/// Counts kept while walking, for the report.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WalkStats {
+    /// Steps from a dog to a food.
+    pub dog_food_steps: u64,
+    /// How many dog to food steps would be expected to have picked a food the dog dislikes if
+    /// dislikes weren't taken out of the graph. Each step adds the share of the dog's foods it
+    /// dislikes, so counting doesn't draw from the walk's `rng`.
+    pub dislike_hits: f64,
+    /// Dog to food steps that first picked a food with an ingredient excluded for the dog and
+    /// had to pick again.
+    pub exclusion_hits: u64,
+}
+
+/// The tokens of one line, starting and ending with `start`, a node of type `start_type`. The
+/// type of each token follows `metapath_from(start_type)`.
+///
+/// Disliked foods aren't in the graph's dog → food edges, so walks never step to them. Walks
+/// also don't step from a dog to a food with an ingredient excluded for it, unless every food
+/// it likes has one. How often they would have done either is added to `stats`.
+///
+/// # Panics
+///
+/// If `start` isn't a node of type `start_type` in `maps`.
+pub fn get_walk_from<'a, G: Graph, R: Rng>(
+    walks_per_line: u8,
+    start_type: NodeType,
+    start: &'a str,
+    mode: WalkMode,
+    maps: &'a G,
+    mut rng: R,
+    stats: &mut WalkStats,
+) -> Vec<&'a str> {
+    let metapath = metapath_from(start_type);
+    let mut relations = [Relation::DogFood; 6];
+    for (index, relation) in relations.iter_mut().enumerate() {
+        *relation = Relation::between(metapath[index], metapath[(index + 1) % metapath.len()])
+            .expect("consecutive metapath types are related");
+    }
+
+    let mut line = Vec::with_capacity(walks_per_line as usize * metapath.len() + 1);
+    line.push(start);
+
+    // The last node of each type, by `NodeType as usize`.
+    let mut last_of_type = [None; 4];
+    last_of_type[start_type as usize] = Some(start);
+
+    let mut node = start;
     for _ in 0..walks_per_line {
-        let food = maps.food_liked_by_dog(dog, &mut rng);
-        line.push(&food);
+        for &relation in relations.iter() {
+            let last = match mode {
+                WalkMode::NonBacktracking => last_of_type[relation.to() as usize],
+                WalkMode::Simple => None,
+            };
+            let not_last = |neighbor: &str| Some(neighbor) != last;
+            let mut next = maps
+                .try_sample_filtered(relation, node, not_last, &mut rng)
+                .unwrap_or_else(|| maps.sample(relation, node, &mut rng));
 
-        let ingredient = maps.ingredient_in_food(food, &mut rng);
-        line.push(&ingredient);
+            if relation == Relation::DogFood {
+                stats.dog_food_steps += 1;
+                let disliked = maps.disliked_edges(node);
+                if disliked > 0 {
+                    let liked = maps.neighbor_list(relation, node).map_or(0, |n| n.len());
+                    stats.dislike_hits += disliked as f64 / (liked + disliked) as f64;
+                }
+                if maps.excludes(node, next) {
+                    stats.exclusion_hits += 1;
+                    let dog = node;
+                    let liked = |food: &str| !maps.excludes(dog, food);
+                    next = maps
+                        .try_sample_filtered(
+                            relation,
+                            dog,
+                            |food| liked(food) && not_last(food),
+                            &mut rng,
+                        )
+                        .or_else(|| maps.try_sample_filtered(relation, dog, liked, &mut rng))
+                        .unwrap_or(next);
+                }
+            }
 
-        let flavor = maps.flavor_for_ingredient(ingredient, &mut rng);
-        line.push(&flavor);
+            node = next;
+            last_of_type[relation.to() as usize] = Some(node);
+            line.push(node);
+        }
+    }
 
-        let ingredient = maps.ingredient_with_flavor(flavor, &mut rng);
-        line.push(&ingredient);
+    line
+}
 
-        let food = maps.food_with_ingredient(ingredient, &mut rng);
-        line.push(&food);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::weighted_mapping;
+    use crate::graph::tests::assert_fits;
+    use crate::mapping::Mapping;
+    use crate::transitions::cycle_distribution;
+    use std::collections::HashMap;
 
-        dog = maps.dog_that_likes_food(food, &mut rng);
-        line.push(&dog);
+    #[test]
+    fn test_walks_per_node_target() {
+        assert_eq!(WalksPerNode::Constant.target(8, 1.0, 1.0), Some(8));
+        let degree = WalksPerNode::Degree;
+        assert_eq!(degree.target(8, degree.weight(10), 5.0), Some(16));
+        assert_eq!(degree.target(8, degree.weight(0), 5.0), Some(1));
+        let log_degree = WalksPerNode::LogDegree;
+        let mean = log_degree.weight(3);
+        assert_eq!(log_degree.target(8, log_degree.weight(3), mean), Some(8));
+        assert_eq!(log_degree.target(8, log_degree.weight(15), mean), Some(16));
+        assert_eq!(WalksPerNode::Adaptive.target(8, 1.0, 1.0), None);
     }
 
-    line.join(" ") + "\n"
+    #[test]
+    fn test_coverage() {
+        let mut coverage = Coverage::default();
+        coverage.add(NodeType::Dog, &["Sparky", "burger", "beef"]);
+        for _ in 0..ADAPTIVE_STABLE_WALKS - 1 {
+            coverage.add(NodeType::Dog, &["Sparky", "burger", "beef"]);
+            assert!(!coverage.is_stable());
+        }
+        coverage.add(NodeType::Dog, &["Sparky", "burger", "beef"]);
+        assert!(coverage.is_stable());
+
+        coverage.add(NodeType::Dog, &["Sparky", "pizza", "beef"]);
+        assert!(!coverage.is_stable());
+    }
+
+    #[test]
+    fn test_non_backtracking() {
+        let mapping = Mapping::new(
+            "Sparky,burger\nMax,burger\n".as_bytes(),
+            "burger,beef\nburger,cheese\n".as_bytes(),
+            "beef,savory\ncheese,savory\n".as_bytes(),
+        );
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let walk = get_walk_from(
+                2,
+                NodeType::Dog,
+                "Sparky",
+                WalkMode::NonBacktracking,
+                &mapping,
+                &mut rng,
+                &mut WalkStats::default(),
+            );
+            // burger is the only food, so walks have to go back to it.
+            assert_eq!(walk[1], "burger");
+            assert_eq!(walk[5], "burger");
+            assert_ne!(walk[4], walk[2]);
+            assert_eq!(walk[6], "Max");
+            assert_eq!(walk[12], "Sparky");
+        }
+    }
+
+    #[test]
+    fn test_dislikes() {
+        let mut mapping = Mapping::new(
+            "Sparky,burger\nSparky,pizza\nMax,burger\n".as_bytes(),
+            "burger,beef\npizza,cheese\n".as_bytes(),
+            "beef,savory\ncheese,savory\n".as_bytes(),
+        );
+        mapping.add_dislike("Sparky", "pizza");
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut stats = WalkStats::default();
+        for _ in 0..20 {
+            let walk = get_walk_from(
+                4,
+                NodeType::Dog,
+                "Sparky",
+                WalkMode::Simple,
+                &mapping,
+                &mut rng,
+                &mut stats,
+            );
+            for pair in walk.windows(2).step_by(6) {
+                assert_eq!(pair[1], "burger");
+            }
+        }
+        assert_eq!(stats.dog_food_steps, 80);
+        // Half of every step from Sparky, which each walk starts with, and none from Max.
+        assert!(stats.dislike_hits >= 10.0 && stats.dislike_hits <= 40.0);
+        assert_eq!(stats.dislike_hits.fract(), 0.0);
+        assert_eq!(stats.exclusion_hits, 0);
+    }
+
+    #[test]
+    fn test_dislikes_every_liked_food() {
+        let mut mapping = Mapping::new(
+            "Sparky,burger\nSparky,pizza\nMax,burger\nRex,pizza\n".as_bytes(),
+            "burger,beef\npizza,cheese\n".as_bytes(),
+            "beef,savory\ncheese,savory\n".as_bytes(),
+        );
+        mapping.add_dislike("Max", "burger");
+        mapping.add_dislike("Rex", "pizza");
+        assert_eq!(
+            mapping.node_ids(NodeType::Dog).collect::<Vec<_>>(),
+            ["Sparky"]
+        );
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut stats = WalkStats::default();
+        for &start_type in NodeType::ALL.iter() {
+            for start in mapping.node_ids(start_type) {
+                for _ in 0..20 {
+                    let walk = get_walk_from(
+                        4,
+                        start_type,
+                        start,
+                        WalkMode::Simple,
+                        &mapping,
+                        &mut rng,
+                        &mut stats,
+                    );
+                    assert!(!walk.contains(&"Max") && !walk.contains(&"Rex"));
+                }
+            }
+        }
+        assert_eq!(stats.dislike_hits, 0.0);
+
+        assert!(mapping.remove_dislike("Rex", "pizza"));
+        let mut dogs: Vec<_> = mapping.node_ids(NodeType::Dog).collect();
+        dogs.sort_unstable();
+        assert_eq!(dogs, ["Rex", "Sparky"]);
+    }
+
+    #[test]
+    fn test_exclusions() {
+        let mut mapping = Mapping::new(
+            "Sparky,burger\nSparky,pizza\nSparky,taco\nMax,taco\n".as_bytes(),
+            "burger,cheese\nburger,beef\npizza,cheese\ntaco,beef\n".as_bytes(),
+            "cheese,salty\nbeef,savory\n".as_bytes(),
+        );
+        mapping.add_exclusion("Sparky", "cheese");
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut stats = WalkStats::default();
+        for _ in 0..20 {
+            let walk = get_walk_from(
+                4,
+                NodeType::Dog,
+                "Sparky",
+                WalkMode::Simple,
+                &mapping,
+                &mut rng,
+                &mut stats,
+            );
+            for pair in walk.windows(2).step_by(6) {
+                assert_eq!(pair[1], "taco");
+            }
+        }
+        assert!(stats.exclusion_hits > 0);
+        assert_eq!(stats.dislike_hits, 0.0);
+    }
+
+    #[test]
+    fn test_walk_shape() {
+        let mapping = weighted_mapping();
+        let mut rng = StdRng::seed_from_u64(49);
+        for &start_type in NodeType::ALL.iter() {
+            let metapath = metapath_from(start_type);
+            for start in mapping.node_ids(start_type) {
+                for walks_per_line in 1..=4 {
+                    for &mode in [WalkMode::Simple, WalkMode::NonBacktracking].iter() {
+                        let walk = get_walk_from(
+                            walks_per_line,
+                            start_type,
+                            start,
+                            mode,
+                            &mapping,
+                            &mut rng,
+                            &mut WalkStats::default(),
+                        );
+                        assert_eq!(walk.len(), 6 * walks_per_line as usize + 1);
+                        assert_eq!(walk[0], start);
+                        for (position, pair) in walk.windows(2).enumerate() {
+                            let from = metapath[position % metapath.len()];
+                            let to = metapath[(position + 1) % metapath.len()];
+                            let relation = Relation::between(from, to).unwrap();
+                            let neighbors = mapping.neighbor_list(relation, pair[0]);
+                            assert!(
+                                neighbors.is_some_and(|neighbors| neighbors
+                                    .iter()
+                                    .any(|neighbor| neighbor == pair[1])),
+                                "{} {} → {} {} isn't an edge",
+                                from,
+                                pair[0],
+                                to,
+                                pair[1]
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_walks_match_cycle_distribution() {
+        let mapping = weighted_mapping();
+        let exact = cycle_distribution(&mapping, "Sparky", 2).unwrap();
+
+        let mut rng = StdRng::seed_from_u64(49);
+        let (mut foods, mut dogs) = (HashMap::new(), HashMap::new());
+        for _ in 0..4000 {
+            let walk = get_walk(2, "Sparky", &mapping, &mut rng);
+            *foods.entry(walk[11]).or_default() += 1;
+            *dogs.entry(walk[12]).or_default() += 1;
+        }
+        assert_fits(&foods, &exact.foods);
+        assert_fits(&dogs, &exact.dogs);
+    }
 }
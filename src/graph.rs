@@ -0,0 +1,367 @@
+use super::mapping::{NodeType, Relation};
+use rand::Rng;
+
+/// The neighbors of one node, borrowed from whichever `Graph` they came from.
+#[derive(Clone, Copy)]
+pub struct Neighbors<'a>(Ids<'a>);
+
+#[derive(Clone, Copy)]
+enum Ids<'a> {
+    Names(&'a [String]),
+    /// Little-endian `u32` IDs of nodes, named by the `NodeNames`.
+    Numbers(&'a dyn NodeNames, &'a [u8]),
+}
+
+/// Names nodes by number, for graphs that store their neighbors as numbers.
+pub(crate) trait NodeNames {
+    fn node_name(&self, id: u32) -> &str;
+}
+
+impl<'a> Neighbors<'a> {
+    pub(crate) fn names(ids: &'a [String]) -> Self {
+        Neighbors(Ids::Names(ids))
+    }
+
+    /// `ids` are little-endian `u32` node IDs that `names` has the names of.
+    pub(crate) fn numbers(names: &'a dyn NodeNames, ids: &'a [u8]) -> Self {
+        Neighbors(Ids::Numbers(names, ids))
+    }
+
+    pub fn len(&self) -> usize {
+        match self.0 {
+            Ids::Names(ids) => ids.len(),
+            Ids::Numbers(_, ids) => ids.len() / 4,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// # Panics
+    ///
+    /// If `index` isn't less than `len()`.
+    pub fn get(&self, index: usize) -> &'a str {
+        match self.0 {
+            Ids::Names(ids) => &ids[index],
+            Ids::Numbers(names, ids) => names.node_name(read_u32(ids, index)),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a str> {
+        let neighbors = *self;
+        (0..self.len()).map(move |index| neighbors.get(index))
+    }
+}
+
+pub(crate) fn read_u32(bytes: &[u8], index: usize) -> u32 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[index * 4..index * 4 + 4]);
+    u32::from_le_bytes(word)
+}
+
+/// Read access to the dog → food → ingredient → flavor graph, however it's stored. Walks,
+/// recommendations and the service work with any `Graph`.
+///
+/// Every sampler picks a neighbor uniformly at random. The `try_` samplers return `None` for
+/// IDs that aren't in the graph, and the others panic, which is fine for IDs that came from the
+/// graph itself, like the ones returned by the other samplers.
+pub trait Graph {
+    /// The nodes `id` is related to, or `None` if `id` isn't a node of type `relation.from()`.
+    fn neighbor_list(&self, relation: Relation, id: &str) -> Option<Neighbors<'_>>;
+
+    /// Every node of the given type, in no particular order.
+    fn node_ids(&self, node_type: NodeType) -> Box<dyn Iterator<Item = &str> + '_>;
+
+    /// Whether `dog` refused or is allergic to `food`. Graphs without dislikes never do.
+    fn dislikes(&self, _dog: &str, _food: &str) -> bool {
+        false
+    }
+
+    /// How many edges from `dog` to foods it dislikes were taken out of the graph, counting
+    /// repeats. Graphs without dislikes have none.
+    fn disliked_edges(&self, _dog: &str) -> usize {
+        0
+    }
+
+    /// Whether `food` has an ingredient `dog` can't have. Graphs without exclusions never do.
+    fn excludes(&self, _dog: &str, _food: &str) -> bool {
+        false
+    }
+
+    /// Whether any of `food`'s ingredients match `predicate`. Unknown foods have none.
+    fn any_ingredient<F: Fn(&str) -> bool>(&self, food: &str, predicate: F) -> bool {
+        self.neighbor_list(Relation::FoodIngredient, food)
+            .is_some_and(|ingredients| ingredients.iter().any(predicate))
+    }
+
+    /// Whether walks and recommendations should keep `dog` away from `food`.
+    fn avoids(&self, dog: &str, food: &str) -> bool {
+        self.dislikes(dog, food) || self.excludes(dog, food)
+    }
+
+    /// Picks one of `id`'s neighbors, or returns `None` if `id` isn't a node of type
+    /// `relation.from()` or has no neighbors.
+    fn try_sample<R: Rng>(&self, relation: Relation, id: &str, rng: &mut R) -> Option<&str> {
+        let neighbors = self.neighbor_list(relation, id)?;
+        if neighbors.is_empty() {
+            return None;
+        }
+        Some(neighbors.get(rng.gen_range(0, neighbors.len())))
+    }
+
+    /// Picks one of `id`'s neighbors for which `allowed` is true, or returns `None` if there
+    /// aren't any or `id` isn't a node of type `relation.from()`.
+    fn try_sample_filtered<R, F>(
+        &self,
+        relation: Relation,
+        id: &str,
+        allowed: F,
+        rng: &mut R,
+    ) -> Option<&str>
+    where
+        R: Rng,
+        F: Fn(&str) -> bool,
+    {
+        let neighbors = self.neighbor_list(relation, id)?;
+        if neighbors.is_empty() {
+            return None;
+        }
+        // Usually most neighbors are allowed, so try a few picks before counting them.
+        for _ in 0..4 {
+            let neighbor = neighbors.get(rng.gen_range(0, neighbors.len()));
+            if allowed(neighbor) {
+                return Some(neighbor);
+            }
+        }
+        let count = neighbors
+            .iter()
+            .filter(|&neighbor| allowed(neighbor))
+            .count();
+        if count == 0 {
+            return None;
+        }
+        let index = rng.gen_range(0, count);
+        neighbors
+            .iter()
+            .filter(|&neighbor| allowed(neighbor))
+            .nth(index)
+    }
+
+    /// # Panics
+    ///
+    /// If `id` isn't a node of type `relation.from()` with neighbors.
+    fn sample<R: Rng>(&self, relation: Relation, id: &str, rng: &mut R) -> &str {
+        match self.try_sample(relation, id, rng) {
+            Some(neighbor) => neighbor,
+            None => panic!("no {} with ID {:?} and any neighbors", relation.from(), id),
+        }
+    }
+
+    fn food_liked_by_dog<R: Rng>(&self, dog: &str, rng: &mut R) -> &str {
+        self.sample(Relation::DogFood, dog, rng)
+    }
+
+    fn ingredient_in_food<R: Rng>(&self, food: &str, rng: &mut R) -> &str {
+        self.sample(Relation::FoodIngredient, food, rng)
+    }
+
+    fn flavor_for_ingredient<R: Rng>(&self, ingredient: &str, rng: &mut R) -> &str {
+        self.sample(Relation::IngredientFlavor, ingredient, rng)
+    }
+
+    fn ingredient_with_flavor<R: Rng>(&self, flavor: &str, rng: &mut R) -> &str {
+        self.sample(Relation::FlavorIngredient, flavor, rng)
+    }
+
+    fn food_with_ingredient<R: Rng>(&self, ingredient: &str, rng: &mut R) -> &str {
+        self.sample(Relation::IngredientFood, ingredient, rng)
+    }
+
+    fn dog_that_likes_food<R: Rng>(&self, food: &str, rng: &mut R) -> &str {
+        self.sample(Relation::FoodDog, food, rng)
+    }
+
+    fn try_food_liked_by_dog<R: Rng>(&self, dog: &str, rng: &mut R) -> Option<&str> {
+        self.try_sample(Relation::DogFood, dog, rng)
+    }
+
+    fn try_ingredient_in_food<R: Rng>(&self, food: &str, rng: &mut R) -> Option<&str> {
+        self.try_sample(Relation::FoodIngredient, food, rng)
+    }
+
+    fn try_flavor_for_ingredient<R: Rng>(&self, ingredient: &str, rng: &mut R) -> Option<&str> {
+        self.try_sample(Relation::IngredientFlavor, ingredient, rng)
+    }
+
+    fn try_ingredient_with_flavor<R: Rng>(&self, flavor: &str, rng: &mut R) -> Option<&str> {
+        self.try_sample(Relation::FlavorIngredient, flavor, rng)
+    }
+
+    fn try_food_with_ingredient<R: Rng>(&self, ingredient: &str, rng: &mut R) -> Option<&str> {
+        self.try_sample(Relation::IngredientFood, ingredient, rng)
+    }
+
+    fn try_dog_that_likes_food<R: Rng>(&self, food: &str, rng: &mut R) -> Option<&str> {
+        self.try_sample(Relation::FoodDog, food, rng)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::fixtures::weighted_mapping;
+    use crate::mapping::Mapping;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashMap;
+
+    /// How many times each node's samplers are run.
+    const SAMPLES: usize = 4000;
+
+    /// Asserts that `observed` counts could have come from `expected` probabilities, with a
+    /// chi-squared test at the 0.1% level, and that nothing outside `expected` was observed.
+    pub(crate) fn assert_fits(observed: &HashMap<&str, usize>, expected: &HashMap<&str, f64>) {
+        for id in observed.keys() {
+            assert!(expected.contains_key(id), "{} should never be picked", id);
+        }
+        if expected.len() < 2 {
+            return;
+        }
+
+        let total = observed.values().sum::<usize>() as f64;
+        let statistic: f64 = expected
+            .iter()
+            .map(|(id, probability)| {
+                let expected = probability * total;
+                let observed = *observed.get(id).unwrap_or(&0) as f64;
+                (observed - expected).powi(2) / expected
+            })
+            .sum();
+        // The Wilson–Hilferty approximation of the 99.9th percentile of chi-squared.
+        let degrees = (expected.len() - 1) as f64;
+        let spread = 2.0 / (9.0 * degrees);
+        let critical = degrees * (1.0 - spread + 3.09 * spread.sqrt()).powi(3);
+        assert!(
+            statistic < critical,
+            "chi-squared {} isn't under {} for {:?} against {:?}",
+            statistic,
+            critical,
+            observed,
+            expected
+        );
+    }
+
+    /// Every neighbor in the list equally likely, so ones listed twice are twice as likely.
+    fn uniform(neighbors: Neighbors<'_>) -> HashMap<&str, f64> {
+        let mut expected = HashMap::new();
+        for neighbor in neighbors.iter() {
+            *expected.entry(neighbor).or_default() += 1.0 / neighbors.len() as f64;
+        }
+        expected
+    }
+
+    fn counts<'a>(mut sample: impl FnMut() -> &'a str) -> HashMap<&'a str, usize> {
+        let mut counts = HashMap::new();
+        for _ in 0..SAMPLES {
+            *counts.entry(sample()).or_default() += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_samplers_follow_edges_uniformly() {
+        let mapping = weighted_mapping();
+        let mut rng = StdRng::seed_from_u64(49);
+        for &relation in Relation::ALL.iter() {
+            for id in mapping.node_ids(relation.from()) {
+                let neighbors = mapping.neighbor_list(relation, id).unwrap();
+                let observed = counts(|| mapping.sample(relation, id, &mut rng));
+                assert_fits(&observed, &uniform(neighbors));
+            }
+        }
+    }
+
+    #[test]
+    fn test_repeated_edges_are_weighted() {
+        let mapping = weighted_mapping();
+        let mut rng = StdRng::seed_from_u64(49);
+
+        let observed = counts(|| mapping.food_liked_by_dog("Sparky", &mut rng));
+        let expected = [("burger", 0.25), ("pizza", 0.25), ("taco", 0.5)];
+        assert_fits(&observed, &expected.iter().copied().collect());
+
+        let observed = counts(|| {
+            mapping
+                .try_sample_filtered(
+                    Relation::DogFood,
+                    "Sparky",
+                    |food| food != "burger",
+                    &mut rng,
+                )
+                .unwrap()
+        });
+        let expected = [("pizza", 1.0 / 3.0), ("taco", 2.0 / 3.0)];
+        assert_fits(&observed, &expected.iter().copied().collect());
+    }
+
+    #[test]
+    fn test_named_samplers() {
+        type Sampler = for<'a> fn(&'a Mapping, &str, &mut StdRng) -> &'a str;
+        type TrySampler = for<'a> fn(&'a Mapping, &str, &mut StdRng) -> Option<&'a str>;
+        let samplers: [(Relation, &str, Sampler, TrySampler); 6] = [
+            (
+                Relation::DogFood,
+                "Sparky",
+                Mapping::food_liked_by_dog,
+                Mapping::try_food_liked_by_dog,
+            ),
+            (
+                Relation::FoodIngredient,
+                "taco",
+                Mapping::ingredient_in_food,
+                Mapping::try_ingredient_in_food,
+            ),
+            (
+                Relation::IngredientFlavor,
+                "cheese",
+                Mapping::flavor_for_ingredient,
+                Mapping::try_flavor_for_ingredient,
+            ),
+            (
+                Relation::FlavorIngredient,
+                "savory",
+                Mapping::ingredient_with_flavor,
+                Mapping::try_ingredient_with_flavor,
+            ),
+            (
+                Relation::IngredientFood,
+                "cheese",
+                Mapping::food_with_ingredient,
+                Mapping::try_food_with_ingredient,
+            ),
+            (
+                Relation::FoodDog,
+                "taco",
+                Mapping::dog_that_likes_food,
+                Mapping::try_dog_that_likes_food,
+            ),
+        ];
+
+        let mapping = weighted_mapping();
+        for &(relation, id, sampler, try_sampler) in samplers.iter() {
+            // The same RNG makes the same picks as `sample`.
+            let mut rngs = [
+                StdRng::seed_from_u64(49),
+                StdRng::seed_from_u64(49),
+                StdRng::seed_from_u64(49),
+            ];
+            for _ in 0..100 {
+                let expected = mapping.sample(relation, id, &mut rngs[0]);
+                assert_eq!(sampler(&mapping, id, &mut rngs[1]), expected);
+                assert_eq!(try_sampler(&mapping, id, &mut rngs[2]), Some(expected));
+            }
+            assert_eq!(try_sampler(&mapping, "Nobody", &mut rngs[0]), None);
+        }
+    }
+}
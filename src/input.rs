@@ -0,0 +1,509 @@
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+/// One of the three source files read by `Mapping::new`, or the optional files of foods dogs
+/// dislike and ingredients they can't have, read by `Mapping::read_dislikes` and
+/// `Mapping::read_exclusions`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum InputFile {
+    DogFood,
+    FoodIngredient,
+    IngredientFlavor,
+    DogFoodDislikes,
+    DogIngredientExclusions,
+}
+
+impl InputFile {
+    /// The names a header row would use for the two columns of this file.
+    pub fn column_names(self) -> (&'static str, &'static str) {
+        match self {
+            InputFile::DogFood | InputFile::DogFoodDislikes => ("dog", "food"),
+            InputFile::FoodIngredient => ("food", "ingredient"),
+            InputFile::IngredientFlavor => ("ingredient", "flavor"),
+            InputFile::DogIngredientExclusions => ("dog", "ingredient"),
+        }
+    }
+}
+
+impl fmt::Display for InputFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (left, right) = self.column_names();
+        write!(f, "{}_{}", left, right)?;
+        match self {
+            InputFile::DogFoodDislikes => f.write_str("_dislikes"),
+            InputFile::DogIngredientExclusions => f.write_str("_exclusions"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Where `write_file_with_progress` reads the graph from.
+#[derive(Clone, Debug)]
+pub struct InputFilenames {
+    pub dog_food: String,
+    pub food_ingredient: String,
+    pub ingredient_flavor: String,
+    /// Foods dogs refused or are allergic to, as `dog,food` rows.
+    pub dog_food_dislikes: Option<String>,
+    /// Ingredients dogs can't have, as `dog,ingredient` rows.
+    pub dog_ingredient_exclusions: Option<String>,
+}
+
+impl InputFilenames {
+    pub fn new(dog_food: &str, food_ingredient: &str, ingredient_flavor: &str) -> Self {
+        InputFilenames {
+            dog_food: dog_food.to_owned(),
+            food_ingredient: food_ingredient.to_owned(),
+            ingredient_flavor: ingredient_flavor.to_owned(),
+            dog_food_dislikes: None,
+            dog_ingredient_exclusions: None,
+        }
+    }
+}
+
+/// Whether the first row of a file is a header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Header {
+    /// The first row is a header if it names the expected (or selected) columns.
+    Auto,
+    Present,
+    Absent,
+}
+
+impl FromStr for Header {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Header::Auto),
+            "yes" | "present" => Ok(Header::Present),
+            "no" | "absent" => Ok(Header::Absent),
+            _ => Err(format!("expected auto, yes or no but got {:?}", s)),
+        }
+    }
+}
+
+/// How to read the source files. The default reads the simple `left,right` format with or
+/// without a header row.
+#[derive(Clone, Debug)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    pub header: Header,
+    /// Header names of the two ID columns, to select them in files with more columns. The
+    /// dislikes file uses the same columns as the dog_food file.
+    pub dog_food_columns: Option<(String, String)>,
+    pub food_ingredient_columns: Option<(String, String)>,
+    pub ingredient_flavor_columns: Option<(String, String)>,
+    pub dog_ingredient_columns: Option<(String, String)>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: b',',
+            header: Header::Auto,
+            dog_food_columns: None,
+            food_ingredient_columns: None,
+            ingredient_flavor_columns: None,
+            dog_ingredient_columns: None,
+        }
+    }
+}
+
+impl CsvOptions {
+    fn columns(&self, input: InputFile) -> Option<(&str, &str)> {
+        let columns = match input {
+            InputFile::DogFood | InputFile::DogFoodDislikes => &self.dog_food_columns,
+            InputFile::FoodIngredient => &self.food_ingredient_columns,
+            InputFile::IngredientFlavor => &self.ingredient_flavor_columns,
+            InputFile::DogIngredientExclusions => &self.dog_ingredient_columns,
+        };
+        columns
+            .as_ref()
+            .map(|(left, right)| (left.as_str(), right.as_str()))
+    }
+
+    /// The names of the two columns of `input` that hold IDs.
+    pub(crate) fn column_names(&self, input: InputFile) -> (&str, &str) {
+        self.columns(input).unwrap_or_else(|| input.column_names())
+    }
+}
+
+/// Parses a delimiter given on the command line. Accepts a single ASCII character, `\t` or
+/// `tab`.
+pub fn parse_delimiter(s: &str) -> Result<u8, String> {
+    match s {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ if s.len() == 1 && s.is_ascii() => Ok(s.as_bytes()[0]),
+        _ => Err(format!("expected a single ASCII character but got {:?}", s)),
+    }
+}
+
+/// Parses a pair of column names given on the command line as `left,right`.
+pub fn parse_columns(s: &str) -> Result<(String, String), String> {
+    let mut names = s.split(',');
+    match (names.next(), names.next(), names.next()) {
+        (Some(left), Some(right), None) if !left.is_empty() && !right.is_empty() => {
+            Ok((left.to_owned(), right.to_owned()))
+        }
+        _ => Err(format!(
+            "expected two column names like dog,food but got {:?}",
+            s
+        )),
+    }
+}
+
+/// The command line options for `CsvOptions`, shared by every binary that reads the source
+/// files. Flatten it into a binary's options with `#[structopt(flatten)]`.
+#[derive(Clone, Debug, StructOpt)]
+pub struct CsvArgs {
+    #[structopt(
+        long = "delimiter",
+        help = "A single character, or \"tab\" for TSV.",
+        default_value = ",",
+        parse(try_from_str = parse_delimiter)
+    )]
+    delimiter: u8,
+
+    #[structopt(
+        long = "header",
+        help = "Whether the files have a header row: auto, yes or no.",
+        default_value = "auto"
+    )]
+    header: Header,
+
+    #[structopt(
+        long = "dog-food-columns",
+        help = "Header names of the dog and food columns, like dog_id,food_id.",
+        parse(try_from_str = parse_columns)
+    )]
+    dog_food_columns: Option<(String, String)>,
+
+    #[structopt(
+        long = "food-ingredients-columns",
+        help = "Header names of the food and ingredient columns.",
+        parse(try_from_str = parse_columns)
+    )]
+    food_ingredients_columns: Option<(String, String)>,
+
+    #[structopt(
+        long = "ingredients-flavor-columns",
+        help = "Header names of the ingredient and flavor columns.",
+        parse(try_from_str = parse_columns)
+    )]
+    ingredients_flavor_columns: Option<(String, String)>,
+
+    #[structopt(
+        long = "dog-ingredient-columns",
+        help = "Header names of the dog and ingredient columns of the exclusions file.",
+        parse(try_from_str = parse_columns)
+    )]
+    dog_ingredient_columns: Option<(String, String)>,
+}
+
+impl CsvArgs {
+    pub fn into_options(self) -> CsvOptions {
+        CsvOptions {
+            delimiter: self.delimiter,
+            header: self.header,
+            dog_food_columns: self.dog_food_columns,
+            food_ingredient_columns: self.food_ingredients_columns,
+            ingredient_flavor_columns: self.ingredients_flavor_columns,
+            dog_ingredient_columns: self.dog_ingredient_columns,
+        }
+    }
+}
+
+/// A row that couldn't be read, like one that isn't valid UTF-8, or a header row without the
+/// columns `CsvOptions` selects.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ReadError {
+    pub input: InputFile,
+    /// 1-based, or 0 if the error isn't on a particular line.
+    pub line_number: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.input, self.message)
+    }
+}
+
+/// One data row of a source file.
+pub(crate) struct Record {
+    record: csv::StringRecord,
+    columns: (usize, usize),
+    delimiter: u8,
+}
+
+impl Record {
+    /// 1-based, like an editor would show it.
+    pub fn line_number(&self) -> usize {
+        self.record
+            .position()
+            .map_or(0, |position| position.line() as usize)
+    }
+
+    /// The row as it would be written back out, without any quoting.
+    pub fn line(&self) -> String {
+        let delimiter = char::from(self.delimiter).to_string();
+        self.record.iter().collect::<Vec<_>>().join(&delimiter)
+    }
+
+    /// The two selected IDs, or `None` if the row doesn't have those columns.
+    pub fn ids(&self) -> Option<(&str, &str)> {
+        match (
+            self.record.get(self.columns.0),
+            self.record.get(self.columns.1),
+        ) {
+            (Some(left), Some(right)) => Some((left, right)),
+            _ => None,
+        }
+    }
+
+    /// For rows of a delta file: whether the row adds (`+`) or removes (`-`) an edge, and the
+    /// two selected IDs without the sign. `None` if the row has no sign or is missing a column.
+    pub fn delta(&self) -> Option<(bool, &str, &str)> {
+        let added = match self.record.get(0)?.chars().next()? {
+            '+' => true,
+            '-' => false,
+            _ => return None,
+        };
+        let field = |column| {
+            let field = self.record.get(column)?;
+            Some(if column == 0 { &field[1..] } else { field })
+        };
+        Some((added, field(self.columns.0)?, field(self.columns.1)?))
+    }
+}
+
+/// Reads every data row of `file`. Quoted fields, `\r\n` line endings and blank lines are
+/// handled as described in RFC 4180. A header row, if there is one, is not returned. Rows
+/// that can't be read are errors, and nothing is read after an I/O error or a missing header.
+pub(crate) fn read_records<R: Read>(
+    input: InputFile,
+    file: R,
+    options: &CsvOptions,
+) -> impl Iterator<Item = Result<Record, ReadError>> {
+    let reader = csv::ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(file);
+    let delimiter = options.delimiter;
+    let (left_name, right_name) = options.column_names(input);
+    let (left_name, right_name) = (left_name.to_owned(), right_name.to_owned());
+    let selected = options.columns(input).is_some();
+    let mut header = if selected {
+        Header::Present
+    } else {
+        options.header
+    };
+
+    let mut columns = (0, 1);
+    let mut failed = false;
+    reader.into_records().filter_map(move |record| {
+        if failed {
+            return None;
+        }
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => {
+                failed = matches!(error.kind(), csv::ErrorKind::Io(_));
+                return Some(Err(ReadError {
+                    input,
+                    line_number: error
+                        .position()
+                        .map_or(0, |position| position.line() as usize),
+                    message: error.to_string(),
+                }));
+            }
+        };
+        if header != Header::Absent {
+            let first_row = header;
+            header = Header::Absent;
+
+            let left = record.iter().position(|field| is_name(field, &left_name));
+            let right = record.iter().position(|field| is_name(field, &right_name));
+            match (left, right) {
+                (Some(left), Some(right)) => {
+                    columns = (left, right);
+                    return None;
+                }
+                _ if selected => {
+                    failed = true;
+                    return Some(Err(ReadError {
+                        input,
+                        line_number: 1,
+                        message: format!(
+                            "no header row with columns {} and {}",
+                            left_name, right_name
+                        ),
+                    }));
+                }
+                _ if first_row == Header::Present => return None,
+                _ => {}
+            }
+        }
+
+        Some(Ok(Record {
+            record,
+            columns,
+            delimiter,
+        }))
+    })
+}
+
+pub(crate) fn is_name(field: &str, name: &str) -> bool {
+    field.trim().eq_ignore_ascii_case(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(input: InputFile, file: &str, options: &CsvOptions) -> Vec<(String, String)> {
+        read_records(input, file.as_bytes(), options)
+            .map(|record| {
+                let record = record.unwrap();
+                let (left, right) = record.ids().unwrap();
+                (left.to_owned(), right.to_owned())
+            })
+            .collect()
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(left, right)| (left.to_string(), right.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_read_records_simple_format() {
+        let options = CsvOptions::default();
+        let file = "Sparky,burger\nMax,taco\n";
+
+        assert_eq!(
+            ids(InputFile::DogFood, file, &options),
+            pairs(&[("Sparky", "burger"), ("Max", "taco")])
+        );
+    }
+
+    #[test]
+    fn test_read_records_quoting_and_line_endings() {
+        let options = CsvOptions::default();
+        let file = "dog,food\r\nSparky,\"Chicken, Rice & \"\"Gravy\"\"\"\r\n\r\nMax,taco\r\n";
+
+        assert_eq!(
+            ids(InputFile::DogFood, file, &options),
+            pairs(&[("Sparky", "Chicken, Rice & \"Gravy\""), ("Max", "taco")])
+        );
+    }
+
+    #[test]
+    fn test_read_records_header_detection() {
+        let auto = CsvOptions::default();
+        let absent = CsvOptions {
+            header: Header::Absent,
+            ..CsvOptions::default()
+        };
+        let present = CsvOptions {
+            header: Header::Present,
+            ..CsvOptions::default()
+        };
+
+        assert_eq!(
+            ids(InputFile::DogFood, "Dog,Food\nSparky,burger", &auto),
+            pairs(&[("Sparky", "burger")])
+        );
+        assert_eq!(
+            ids(InputFile::DogFood, "dog,food\nSparky,burger", &absent),
+            pairs(&[("dog", "food"), ("Sparky", "burger")])
+        );
+        assert_eq!(
+            ids(InputFile::DogFood, "name,meal\nSparky,burger", &present),
+            pairs(&[("Sparky", "burger")])
+        );
+    }
+
+    #[test]
+    fn test_read_records_tsv_with_selected_columns() {
+        let options = CsvOptions {
+            delimiter: b'\t',
+            dog_food_columns: Some(("dog_id".to_owned(), "food_id".to_owned())),
+            ..CsvOptions::default()
+        };
+        let file = "food_id\tweight\tdog_id\nburger\t12\tSparky\ntaco\t3\tMax\n";
+
+        assert_eq!(
+            ids(InputFile::DogFood, file, &options),
+            pairs(&[("Sparky", "burger"), ("Max", "taco")])
+        );
+    }
+
+    #[test]
+    fn test_read_records_exclusions_with_selected_columns() {
+        let options = CsvOptions {
+            dog_ingredient_columns: Some(("dog_id".to_owned(), "ingredient_id".to_owned())),
+            ..CsvOptions::default()
+        };
+        let file = "ingredient_id,dog_id\nchicken,Sparky\n";
+
+        assert_eq!(
+            ids(InputFile::DogIngredientExclusions, file, &options),
+            pairs(&[("Sparky", "chicken")])
+        );
+    }
+
+    #[test]
+    fn test_read_records_missing_column() {
+        let options = CsvOptions::default();
+        let records: Vec<Record> = read_records(
+            InputFile::DogFood,
+            "Sparky,burger\nMax".as_bytes(),
+            &options,
+        )
+        .collect::<Result<_, _>>()
+        .unwrap();
+
+        assert_eq!(records[1].line_number(), 2);
+        assert_eq!(records[1].ids(), None);
+    }
+
+    #[test]
+    fn test_read_records_invalid_utf8() {
+        let options = CsvOptions::default();
+        let records: Vec<Result<Record, ReadError>> = read_records(
+            InputFile::DogFood,
+            &b"Sparky,burger\nMax,\xff\nRex,taco\n"[..],
+            &options,
+        )
+        .collect();
+
+        assert_eq!(records.len(), 3);
+        let error = records[1].as_ref().err().unwrap();
+        assert_eq!(error.line_number, 2);
+        assert!(error.to_string().starts_with("dog_food: "));
+        assert_eq!(records[2].as_ref().unwrap().ids(), Some(("Rex", "taco")));
+    }
+
+    #[test]
+    fn test_read_records_selected_columns_without_header() {
+        let options = CsvOptions {
+            dog_food_columns: Some(("dog_id".to_owned(), "food_id".to_owned())),
+            ..CsvOptions::default()
+        };
+        let records: Vec<Result<Record, ReadError>> =
+            read_records(InputFile::DogFood, "Sparky,burger".as_bytes(), &options).collect();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0].as_ref().err().unwrap().to_string(),
+            "dog_food: no header row with columns dog_id and food_id"
+        );
+    }
+}
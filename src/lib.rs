@@ -1,35 +1,655 @@
+//! Builds random walks over a dog → food → ingredient → flavor graph for training dog food
+//! recommendations.
+//!
+//! A panic from this library is a bug unless the function documents it under `# Panics`. Those
+//! functions have fallible alternatives, like the `try_` samplers of `Graph`, for IDs or
+//! input that might not be valid.
+
+mod checkpoint;
+pub mod files;
+#[cfg(test)]
+mod fixtures;
+mod format;
+mod generate;
 mod get_line;
+mod graph;
+mod input;
+mod mapped;
 mod mapping;
+mod output;
+mod paths;
+mod progress;
+mod recommend;
+mod report;
+mod service;
+mod transitions;
+mod validate;
+
+use checkpoint::{Checkpoint, ShardState};
+use format::Vocabulary;
+use get_line::Coverage;
+use output::ShardedWriter;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
-use get_line::get_line;
-use mapping::Mapping;
-use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
+pub use format::Format;
+pub use generate::{generate_source_data, SourceDataOptions};
+pub use get_line::{
+    get_line, get_walk, get_walk_from, WalkMode, WalkOptions, WalkStats, WalksPerNode,
+    ADAPTIVE_STABLE_WALKS,
+};
+pub use graph::{Graph, Neighbors};
+pub use input::{
+    parse_columns, parse_delimiter, CsvArgs, CsvOptions, Header, InputFile, InputFilenames,
+};
+pub use mapped::MappedGraph;
+pub use mapping::{metapath_from, parse_node_types, Delta, Mapping, NodeType, Relation, METAPATH};
+pub use output::{shard_for, OutputOptions, SHARD_PLACEHOLDER};
+pub use paths::{explain, shortest_paths, ExplainedPath, Explanation, Node};
+pub use progress::{Progress, ProgressUpdate, StderrProgress};
+pub use recommend::{recommend_by_embeddings, recommend_by_walks, Embeddings, Recommendation};
+pub use report::{peak_rss_bytes, Report, ReportFormat};
+pub use service::{Response, Service};
+pub use transitions::{cycle_distribution, transition, CycleDistribution, Distribution};
+pub use validate::{validate, validate_with_options, Problem, Reason};
 
+/// Writes `lines_per_dog` walks of `walks_per_line` cycles for every dog, returning a `Report`
+/// of how long each phase took. The walk phase includes encoding and writing each line, and the
+/// flush phase finishing the files.
+///
+/// # Panics
+///
+/// If a file can't be read or written, or a row of a source file can't be read or is missing
+/// a column. `validate` reports rows like that.
 pub fn write_file(
     dog_food_filename: &str,
     food_ingredient_filename: &str,
     ingredient_flavor_filename: &str,
-    output_filename: &str,
+    output: &OutputOptions,
     lines_per_dog: u8,
     walks_per_line: u8,
-) {
-    let mapping = Mapping::new(
-        BufReader::new(File::open(dog_food_filename).unwrap()),
-        BufReader::new(File::open(food_ingredient_filename).unwrap()),
-        BufReader::new(File::open(ingredient_flavor_filename).unwrap()),
+    csv_options: &CsvOptions,
+) -> Report {
+    write_file_with_progress(
+        &InputFilenames::new(
+            dog_food_filename,
+            food_ingredient_filename,
+            ingredient_flavor_filename,
+        ),
+        output,
+        &WalkOptions::new(lines_per_dog, walks_per_line),
+        csv_options,
+        &mut |_: &ProgressUpdate| {},
+    )
+}
+
+/// Like `write_file`, telling `progress` after each dog is written.
+///
+/// Dogs are walked in order of ID. With `output.checkpoint_filename`, which dogs are done is
+/// recorded every `output.checkpoint_every` dogs, and `output.resume` picks up from there. With
+/// `walks.seed` too, a resumed run writes exactly what an uninterrupted one would have.
+///
+/// # Panics
+///
+/// If a file can't be read or written, the checkpoint doesn't match this run, or `walks.dogs`
+/// leaves out some dogs while `walks.start_types` has other types.
+pub fn write_file_with_progress(
+    inputs: &InputFilenames,
+    output: &OutputOptions,
+    walks: &WalkOptions,
+    csv_options: &CsvOptions,
+    progress: &mut dyn Progress,
+) -> Report {
+    let mut report = Report::default();
+    let mut mapping = Mapping::with_report(
+        files::open(&inputs.dog_food).unwrap(),
+        files::open(&inputs.food_ingredient).unwrap(),
+        files::open(&inputs.ingredient_flavor).unwrap(),
+        csv_options,
+        &mut report,
     );
+    if let Some(dog_food_dislikes) = &inputs.dog_food_dislikes {
+        let file = files::open(dog_food_dislikes).unwrap();
+        report.time(&format!("read {}", InputFile::DogFoodDislikes), || {
+            mapping.read_dislikes(file, csv_options)
+        });
+    }
+    if let Some(dog_ingredient_exclusions) = &inputs.dog_ingredient_exclusions {
+        let file = files::open(dog_ingredient_exclusions).unwrap();
+        report.time(
+            &format!("read {}", InputFile::DogIngredientExclusions),
+            || mapping.read_exclusions(file, csv_options),
+        );
+    }
+    match &walks.dogs {
+        Some(dogs) => walk_dogs(
+            &mapping,
+            dogs.iter().map(String::as_str),
+            output,
+            walks,
+            progress,
+            report,
+        ),
+        None => walk_dogs(
+            &mapping,
+            mapping.dogs().map(String::as_str),
+            output,
+            walks,
+            progress,
+            report,
+        ),
+    }
+}
+
+/// Writes walks from just `dogs`, for regenerating a few dogs' walks without walking every
+/// dog. Dogs that aren't in `mapping` are listed in the report's `unknown_dogs` and skipped.
+/// `walks.dogs` is ignored in favor of `dogs`. Otherwise like `write_file_with_progress`.
+///
+/// # Panics
+///
+/// If an output file can't be written, the checkpoint doesn't match this run, or `dogs` leaves
+/// out some dogs while `walks.start_types` has other types, since their walks would all be
+/// rewritten too.
+pub fn write_walks<'a, I>(
+    mapping: &Mapping,
+    dogs: I,
+    output: &OutputOptions,
+    walks: &WalkOptions,
+    progress: &mut dyn Progress,
+) -> Report
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    walk_dogs(mapping, dogs, output, walks, progress, Report::default())
+}
 
-    let mut output_file =
-        BufWriter::with_capacity(4 * 1024 * 1024, File::create(output_filename).unwrap());
+fn walk_dogs<'a, I>(
+    mapping: &Mapping,
+    dogs: I,
+    output: &OutputOptions,
+    walks: &WalkOptions,
+    progress: &mut dyn Progress,
+    mut report: Report,
+) -> Report
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut known_dogs: Vec<&String> = mapping.dogs().collect();
+    known_dogs.sort_unstable();
+    let mut dogs: Vec<&String> = dogs
+        .into_iter()
+        .filter_map(
+            |dog| match known_dogs.binary_search_by(|known| known.as_str().cmp(dog)) {
+                Ok(index) => Some(known_dogs[index]),
+                Err(_) => {
+                    report.unknown_dogs.push(dog.to_owned());
+                    None
+                }
+            },
+        )
+        .collect();
+    dogs.sort_unstable();
+    dogs.dedup();
+    report.unknown_dogs.sort_unstable();
+    report.unknown_dogs.dedup();
+    assert!(
+        dogs.len() == known_dogs.len()
+            || walks
+                .start_types
+                .iter()
+                .all(|&node_type| node_type == NodeType::Dog),
+        "walking only some dogs can't be combined with other start types"
+    );
 
-    for dog in mapping.dogs() {
-        for _ in 0..lines_per_dog {
-            let mut rng = rand::thread_rng();
-            let line = get_line(walks_per_line, &dog, &mapping, &mut rng);
-            output_file.write_all(line.as_bytes()).unwrap();
+    let mut starts: Vec<(NodeType, &str)> = Vec::new();
+    for &node_type in NodeType::ALL.iter() {
+        if !walks.start_types.contains(&node_type) {
+            continue;
         }
+        let mut nodes: Vec<&str> = match node_type {
+            NodeType::Dog => dogs.iter().map(|dog| dog.as_str()).collect(),
+            _ => mapping.nodes(node_type).map(String::as_str).collect(),
+        };
+        nodes.sort_unstable();
+        starts.extend(nodes.into_iter().map(|node| (node_type, node)));
     }
+    // Over every node of each type, not just the ones being walked, so walking a few dogs
+    // gives them as many walks as walking all of them would.
+    let mean_weights: HashMap<NodeType, f64> = NodeType::ALL
+        .iter()
+        .map(|&node_type| {
+            let weights: Vec<f64> = mapping
+                .nodes(node_type)
+                .map(|node| {
+                    let degree = get_line::degree(mapping, node_type, node);
+                    walks.walks_per_node.weight(degree)
+                })
+                .collect();
+            let mean = weights.iter().sum::<f64>() / weights.len().max(1) as f64;
+            (node_type, mean)
+        })
+        .collect();
+
+    let vocabulary = match output.format {
+        Format::Ids => Some(Vocabulary::new(mapping)),
+        Format::Text | Format::Jsonl => None,
+    };
+    let mut checkpoint = Checkpoint {
+        seed: walks.seed,
+        lines_per_dog: walks.lines_per_dog,
+        walks_per_line: walks.walks_per_line,
+        format: output.format.as_str().to_owned(),
+        start_types: starts_description(&walks.start_types),
+        walks_per_node: walks.walks_per_node.as_str().to_owned(),
+        mode: walks.mode.as_str().to_owned(),
+        nodes_done: 0,
+        last_node: None,
+        tokens: 0,
+        stats: WalkStats::default(),
+        shards: Vec::new(),
+    };
+    let mut output_file = match (&output.checkpoint_filename, output.resume) {
+        (Some(checkpoint_filename), true) => {
+            let saved = Checkpoint::load(checkpoint_filename).unwrap();
+            checkpoint.shards = (0..output.shards.max(1))
+                .map(|shard| ShardState {
+                    filename: output.shard_filename(shard),
+                    ..ShardState::default()
+                })
+                .collect();
+            saved.check_matches(&checkpoint).unwrap();
+            let last_node = saved
+                .nodes_done
+                .checked_sub(1)
+                .and_then(|index| starts.get(index))
+                .map(|&(node_type, node)| (node_type, node.to_owned()));
+            assert_eq!(
+                saved.last_node, last_node,
+                "the source files have changed since the checkpoint"
+            );
+            let output_file = ShardedWriter::resume(output, &saved.shards).unwrap();
+            checkpoint = saved;
+            output_file
+        }
+        (None, true) => panic!("resuming needs a checkpoint file"),
+        (_, false) => ShardedWriter::create(output).unwrap(),
+    };
+
+    let mut buffer = Vec::new();
+    // Resumed runs count what the checkpoint already has, so the totals are for the whole
+    // output.
+    let mut bytes: u64 = checkpoint.shards.iter().map(|shard| shard.bytes).sum();
+    let mut lines: u64 = checkpoint.shards.iter().map(|shard| shard.lines).sum();
+    let mut tokens = checkpoint.tokens;
+    let mut nodes_walked = checkpoint.nodes_done as u64;
+    let mut stats = checkpoint.stats.clone();
+    let mut update = ProgressUpdate {
+        nodes_done: checkpoint.nodes_done,
+        nodes_total: starts.len(),
+        lines_written: lines,
+        nodes_resumed: checkpoint.nodes_done,
+        lines_resumed: lines,
+        elapsed: Duration::default(),
+    };
+
+    report.time("walk", || {
+        let start = Instant::now();
+        for &(start_type, node) in &starts[checkpoint.nodes_done..] {
+            let mut rng = walks.rng_for(start_type, node);
+            let target = walks.walks_per_node.target(
+                walks.lines_per_dog,
+                walks
+                    .walks_per_node
+                    .weight(get_line::degree(mapping, start_type, node)),
+                mean_weights[&start_type],
+            );
+            let mut coverage = Coverage::default();
+            let mut index = 0;
+            while match target {
+                Some(target) => index < target,
+                None => index < walks.lines_per_dog.into() && !coverage.is_stable(),
+            } {
+                let walk = get_walk_from(
+                    walks.walks_per_line,
+                    start_type,
+                    node,
+                    walks.mode,
+                    mapping,
+                    &mut rng,
+                    &mut stats,
+                );
+                buffer.clear();
+                format::encode(
+                    output.format,
+                    start_type,
+                    &walk,
+                    index,
+                    vocabulary.as_ref(),
+                    &mut buffer,
+                );
+                output_file.write_line(start_type, node, &buffer).unwrap();
+
+                bytes += buffer.len() as u64;
+                lines += 1;
+                tokens += walk.len() as u64;
+                if target.is_none() {
+                    coverage.add(start_type, &walk);
+                }
+                index += 1;
+            }
+            nodes_walked += 1;
+
+            checkpoint.nodes_done += 1;
+            checkpoint.last_node = Some((start_type, node.to_owned()));
+            checkpoint.tokens = tokens;
+            checkpoint.stats = stats.clone();
+            if let Some(checkpoint_filename) = &output.checkpoint_filename {
+                if checkpoint
+                    .nodes_done
+                    .is_multiple_of(output.checkpoint_every.max(1))
+                {
+                    checkpoint.shards = output_file.checkpoint().unwrap();
+                    checkpoint.save(checkpoint_filename).unwrap();
+                }
+            }
+
+            update.nodes_done = checkpoint.nodes_done;
+            update.lines_written = lines;
+            update.elapsed = start.elapsed();
+            progress.update(&update);
+        }
+        progress.finish(&update);
+    });
+
+    report.time("flush", || {
+        if let Some(checkpoint_filename) = &output.checkpoint_filename {
+            checkpoint.shards = output_file.checkpoint().unwrap();
+            checkpoint.save(checkpoint_filename).unwrap();
+        }
+        output_file.finish().unwrap();
+        if let Some(vocabulary) = vocabulary {
+            vocabulary.write(&output.vocabulary_filename).unwrap();
+        }
+    });
+
+    report.nodes_walked = nodes_walked;
+    report.dog_food_steps = stats.dog_food_steps;
+    report.dislike_hits = stats.dislike_hits;
+    report.exclusion_hits = stats.exclusion_hits;
+    report.bytes_written = bytes;
+    report.lines_written = lines;
+    report.tokens_written = tokens;
+    report.peak_rss_bytes = peak_rss_bytes();
+    report
+}
 
-    output_file.flush().unwrap();
+/// Like `dog,food`.
+fn starts_description(start_types: &[NodeType]) -> String {
+    NodeType::ALL
+        .iter()
+        .filter(|node_type| start_types.contains(node_type))
+        .map(|node_type| node_type.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::env;
+    use std::fs::{self, File};
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn test_resume_matches_uninterrupted_run() {
+        let directory = env::temp_dir().join("dog_food_for_you_resume");
+        fs::create_dir_all(&directory).unwrap();
+        let path = |filename: &str| directory.join(filename).to_str().unwrap().to_owned();
+        let inputs = [
+            path("dog_food.csv"),
+            path("food_ingredient.csv"),
+            path("ingredient_flavor.csv"),
+        ];
+        let options = SourceDataOptions {
+            num_dogs: 20,
+            num_foods: 20,
+            num_ingredients: 5,
+            num_flavors: 3,
+            num_dog_food_lines: 3,
+            num_food_ingredient_lines: 3,
+            num_ingredient_flavor_lines: 2,
+        };
+        let mut files: Vec<File> = inputs
+            .iter()
+            .map(|input| File::create(input).unwrap())
+            .collect();
+        if let [dog_food, food_ingredient, ingredient_flavor] = files.as_mut_slice() {
+            generate_source_data(
+                &options,
+                &mut StdRng::seed_from_u64(1),
+                dog_food,
+                food_ingredient,
+                ingredient_flavor,
+            )
+            .unwrap();
+        }
+        drop(files);
+
+        // Some dislikes and exclusions, so the report has hits to carry over from the checkpoint.
+        let rows = |input: &str| -> Vec<(String, String)> {
+            fs::read_to_string(input)
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    let (left, right) = line.split_once(',').unwrap();
+                    (left.to_owned(), right.to_owned())
+                })
+                .collect()
+        };
+        let dog_food = rows(&inputs[0]);
+        let ingredients: HashMap<String, String> = rows(&inputs[1]).into_iter().collect();
+        let dislikes: String = dog_food
+            .iter()
+            .step_by(3)
+            .map(|(dog, food)| format!("{},{}\n", dog, food))
+            .collect();
+        let exclusions: String = dog_food
+            .iter()
+            .step_by(5)
+            .filter_map(|(dog, food)| Some(format!("{},{}\n", dog, ingredients.get(food)?)))
+            .collect();
+        fs::write(path("dislikes.csv"), dislikes).unwrap();
+        fs::write(path("exclusions.csv"), exclusions).unwrap();
+        let filenames = InputFilenames {
+            dog_food_dislikes: Some(path("dislikes.csv")),
+            dog_ingredient_exclusions: Some(path("exclusions.csv")),
+            ..InputFilenames::new(&inputs[0], &inputs[1], &inputs[2])
+        };
+
+        let walks = WalkOptions {
+            seed: Some(3),
+            ..WalkOptions::new(2, 3)
+        };
+        let run = |output: &OutputOptions, progress: &mut dyn Progress| {
+            write_file_with_progress(&filenames, output, &walks, &CsvOptions::default(), progress)
+        };
+
+        let uninterrupted = OutputOptions {
+            shards: 2,
+            ..OutputOptions::new(&path("uninterrupted.txt"))
+        };
+        let expected = run(&uninterrupted, &mut |_: &ProgressUpdate| {});
+
+        let checkpointed = OutputOptions {
+            shards: 2,
+            checkpoint_filename: Some(path("checkpoint.json")),
+            checkpoint_every: 3,
+            ..OutputOptions::new(&path("resumed.txt"))
+        };
+        let crashed = panic::catch_unwind(AssertUnwindSafe(|| {
+            run(&checkpointed, &mut |update: &ProgressUpdate| {
+                assert!(update.nodes_done < 8, "crash");
+            })
+        }));
+        assert!(crashed.is_err());
+        let report = run(
+            &OutputOptions {
+                resume: true,
+                ..checkpointed.clone()
+            },
+            &mut |_: &ProgressUpdate| {},
+        );
+        assert_eq!(report.nodes_walked, expected.nodes_walked);
+        assert_eq!(report.bytes_written, expected.bytes_written);
+        assert_eq!(report.lines_written, expected.lines_written);
+        assert_eq!(report.tokens_written, expected.tokens_written);
+        assert_eq!(report.dog_food_steps, expected.dog_food_steps);
+        assert_eq!(report.dislike_hits, expected.dislike_hits);
+        assert_eq!(report.exclusion_hits, expected.exclusion_hits);
+        assert!(expected.dislike_hits > 0.0 && expected.exclusion_hits > 0);
+
+        for shard in 0..2 {
+            assert_eq!(
+                fs::read(checkpointed.shard_filename(shard)).unwrap(),
+                fs::read(uninterrupted.shard_filename(shard)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_write_walks_for_some_dogs() {
+        let mapping = Mapping::new(
+            "Sparky,burger\nMax,burger\nRex,pizza\n".as_bytes(),
+            "burger,beef\n".as_bytes(),
+            "beef,savory\n".as_bytes(),
+        );
+        let filename = env::temp_dir()
+            .join("dog_food_for_you_some_dogs.txt")
+            .to_str()
+            .unwrap()
+            .to_owned();
+
+        let report = write_walks(
+            &mapping,
+            vec!["Sparky", "Rex", "Sparky", "Fido"],
+            &OutputOptions::new(&filename),
+            &WalkOptions::new(2, 1),
+            &mut |_: &ProgressUpdate| {},
+        );
+        assert_eq!(report.unknown_dogs, vec!["Fido", "Rex"]);
+        assert_eq!(report.lines_written, 2);
+        let output = fs::read_to_string(&filename).unwrap();
+        assert!(output.lines().all(|line| line.starts_with("Sparky ")));
+    }
+
+    #[test]
+    fn test_write_walks_from_every_type() {
+        let mapping = Mapping::new(
+            "Sparky,burger\nMax,burger\n".as_bytes(),
+            "burger,beef\nburger,cheese\n".as_bytes(),
+            "beef,savory\ncheese,salty\n".as_bytes(),
+        );
+        let filename = env::temp_dir()
+            .join("dog_food_for_you_every_type.jsonl")
+            .to_str()
+            .unwrap()
+            .to_owned();
+
+        let report = write_walks(
+            &mapping,
+            mapping.dogs().map(String::as_str),
+            &OutputOptions {
+                format: Format::Jsonl,
+                ..OutputOptions::new(&filename)
+            },
+            &WalkOptions {
+                start_types: NodeType::ALL.to_vec(),
+                ..WalkOptions::new(1, 2)
+            },
+            &mut |_: &ProgressUpdate| {},
+        );
+        // Two dogs, one food, two ingredients and two flavors.
+        assert_eq!(report.lines_written, 7);
+        let output = fs::read_to_string(&filename).unwrap();
+        let walks: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(walks[2]["food"], "burger");
+        assert_eq!(walks[6]["flavor"], "savory");
+        let flavor_walk = walks[6]["tokens"].as_array().unwrap();
+        assert_eq!(flavor_walk.len(), 13);
+        assert!(["beef", "cheese"].contains(&flavor_walk[1].as_str().unwrap()));
+        assert_eq!(flavor_walk[2], "burger");
+        assert!(["Max", "Sparky"].contains(&flavor_walk[3].as_str().unwrap()));
+    }
+
+    #[test]
+    #[should_panic(expected = "walking only some dogs can't be combined with other start types")]
+    fn test_write_walks_for_some_dogs_from_every_type() {
+        let mapping = Mapping::new(
+            "Sparky,burger\nMax,burger\n".as_bytes(),
+            "burger,beef\n".as_bytes(),
+            "beef,savory\n".as_bytes(),
+        );
+        let filename = env::temp_dir()
+            .join("dog_food_for_you_some_dogs_every_type.txt")
+            .to_str()
+            .unwrap()
+            .to_owned();
+
+        write_walks(
+            &mapping,
+            vec!["Sparky"],
+            &OutputOptions::new(&filename),
+            &WalkOptions {
+                start_types: NodeType::ALL.to_vec(),
+                ..WalkOptions::new(1, 1)
+            },
+            &mut |_: &ProgressUpdate| {},
+        );
+    }
+
+    #[test]
+    fn test_walks_per_node() {
+        // Sparky likes three foods and Max one, so the mean degree is two.
+        let mapping = Mapping::new(
+            "Sparky,burger\nSparky,pizza\nSparky,taco\nMax,burger\n".as_bytes(),
+            "burger,beef\npizza,beef\ntaco,beef\n".as_bytes(),
+            "beef,savory\n".as_bytes(),
+        );
+        let filename = env::temp_dir()
+            .join("dog_food_for_you_walks_per_node.txt")
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let run = |walks_per_node: WalksPerNode| {
+            write_walks(
+                &mapping,
+                mapping.dogs().map(String::as_str),
+                &OutputOptions::new(&filename),
+                &WalkOptions {
+                    walks_per_node,
+                    ..WalkOptions::new(4, 1)
+                },
+                &mut |_: &ProgressUpdate| {},
+            );
+            let output = fs::read_to_string(&filename).unwrap();
+            let count = |dog: &str| {
+                output
+                    .lines()
+                    .filter(|line| line.starts_with(&format!("{} ", dog)))
+                    .count()
+            };
+            (count("Sparky"), count("Max"))
+        };
+
+        assert_eq!(run(WalksPerNode::Constant), (4, 4));
+        assert_eq!(run(WalksPerNode::Degree), (6, 2));
+        let (sparky, max) = run(WalksPerNode::Adaptive);
+        assert!(sparky <= 4 && max <= 4);
+        assert!(max >= ADAPTIVE_STABLE_WALKS);
+    }
 }
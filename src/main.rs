@@ -1,8 +1,17 @@
 extern crate structopt;
 
+use dog_food_for_you::{
+    files, CsvArgs, Format, InputFilenames, NodeType, OutputOptions, Progress, ProgressUpdate,
+    ReportFormat, StderrProgress, WalkMode, WalkOptions, WalksPerNode,
+};
+use std::io::Read;
 use std::time::Instant;
 use structopt::StructOpt;
 
+/// An alias so structopt parses `--start-types` as one comma separated value instead of a
+/// repeated option.
+type NodeTypes = Vec<NodeType>;
+
 #[derive(StructOpt, Debug)]
 #[structopt(
     name = "dog_food_for_you",
@@ -40,22 +49,237 @@ struct Opt {
     )]
     ingredients_flavor_filename: String,
 
-    #[structopt(short = "o", long = "output-file", default_value = "output.txt")]
+    #[structopt(
+        long = "dog-food-dislikes-file",
+        help = "Dog and food pairs the dog dislikes. Walks never step from a dog to a food it \
+                dislikes."
+    )]
+    dog_food_dislikes_filename: Option<String>,
+
+    #[structopt(
+        long = "dog-ingredient-exclusions-file",
+        help = "Dog and ingredient pairs for ingredients the dog can't have. Walks never step from \
+                a dog to a food with one."
+    )]
+    dog_ingredient_exclusions_filename: Option<String>,
+
+    #[structopt(flatten)]
+    csv: CsvArgs,
+
+    #[structopt(
+        short = "o",
+        long = "output-file",
+        help = "Compressed if it ends in .gz or .zst. - writes to stdout.",
+        default_value = "output.txt"
+    )]
     output_filename: String,
+
+    #[structopt(
+        long = "shards",
+        help = "Splits the output into this many files. Each dog's walks always go to the same \
+                shard. The output file name should contain {shard} when there's more than one.",
+        default_value = "1"
+    )]
+    shards: u32,
+
+    #[structopt(
+        long = "manifest-file",
+        help = "Writes a list of the output files with their line counts and CRC-32s."
+    )]
+    manifest_filename: Option<String>,
+
+    #[structopt(
+        long = "format",
+        help = "text, jsonl or ids. ids writes little-endian u32s and a vocabulary file.",
+        default_value = "text"
+    )]
+    format: Format,
+
+    #[structopt(long = "vocabulary-file", default_value = "vocabulary.tsv")]
+    vocabulary_filename: String,
+
+    #[structopt(
+        long = "report",
+        help = "Prints how long each phase took, peak memory and throughput to stderr, as text or json."
+    )]
+    report: Option<ReportFormat>,
+
+    #[structopt(
+        long = "progress",
+        help = "Shows dogs completed, lines written, throughput and time left on stderr."
+    )]
+    progress: bool,
+
+    #[structopt(
+        long = "seed",
+        help = "Writes the same walks every time for the same seed and source files."
+    )]
+    seed: Option<u64>,
+
+    #[structopt(
+        long = "checkpoint-file",
+        help = "Records which dogs have been written, so an interrupted run can be resumed. \
+                The output has to be uncompressed files."
+    )]
+    checkpoint_filename: Option<String>,
+
+    #[structopt(
+        long = "checkpoint-every",
+        help = "How many dogs to write between checkpoints.",
+        default_value = "1000"
+    )]
+    checkpoint_every: usize,
+
+    #[structopt(
+        long = "resume",
+        help = "Continues from the checkpoint file, appending to the output. With --seed the \
+                output is the same as an uninterrupted run's."
+    )]
+    resume: bool,
+
+    #[structopt(
+        long = "dog",
+        help = "Only writes walks from this dog. Can be given more than once."
+    )]
+    dogs: Vec<String>,
+
+    #[structopt(
+        long = "dogs-file",
+        help = "Only writes walks from the dogs in this file, one ID per line."
+    )]
+    dogs_filename: Option<String>,
+
+    #[structopt(
+        long = "start-types",
+        help = "The types of node to start walks from, like dog,food, or all.",
+        default_value = "dog",
+        parse(try_from_str = dog_food_for_you::parse_node_types)
+    )]
+    start_types: NodeTypes,
+
+    #[structopt(
+        long = "walks-per-node",
+        help = "How many walks to take from each start node: constant (--lines-per-dog from \
+                each), degree or log-degree (in proportion, --lines-per-dog on average) or \
+                adaptive (until new walks stop finding new nodes, at most --lines-per-dog).",
+        default_value = "constant"
+    )]
+    walks_per_node: WalksPerNode,
+
+    #[structopt(
+        long = "non-backtracking",
+        help = "Never steps back to the last node of the same type, like the dog a walk just \
+                came from, unless it's the only choice."
+    )]
+    non_backtracking: bool,
+}
+
+impl Opt {
+    /// The dogs to walk from, if only some of them should be.
+    fn selected_dogs(&self) -> Option<Vec<String>> {
+        let mut dogs = self.dogs.clone();
+        if let Some(dogs_filename) = &self.dogs_filename {
+            let mut contents = String::new();
+            files::open(dogs_filename)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            dogs.extend(
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|dog| !dog.is_empty())
+                    .map(str::to_owned),
+            );
+        }
+
+        if dogs.is_empty() && self.dogs_filename.is_none() {
+            None
+        } else {
+            Some(dogs)
+        }
+    }
 }
 
 fn main() {
     let start = Instant::now();
     let opt = Opt::from_args();
 
-    dog_food_for_you::write_file(
+    let mut inputs = vec![
         &opt.dog_food_filename,
         &opt.food_ingredients_filename,
         &opt.ingredients_flavor_filename,
-        &opt.output_filename,
-        opt.lines_per_dog,
-        opt.walks_per_line,
+    ];
+    inputs.extend(&opt.dogs_filename);
+    inputs.extend(&opt.dog_food_dislikes_filename);
+    inputs.extend(&opt.dog_ingredient_exclusions_filename);
+    if inputs
+        .iter()
+        .filter(|&&input| input == files::STANDARD_STREAM)
+        .count()
+        > 1
+    {
+        panic!("only one input file can be read from stdin")
+    }
+    if opt.output_filename == files::STANDARD_STREAM && opt.shards > 1 {
+        panic!("sharded output can't be written to stdout")
+    }
+
+    let mut stderr_progress = StderrProgress::new();
+    let mut no_progress = |_: &ProgressUpdate| {};
+    let progress: &mut dyn Progress = if opt.progress {
+        &mut stderr_progress
+    } else {
+        &mut no_progress
+    };
+    let report = dog_food_for_you::write_file_with_progress(
+        &InputFilenames {
+            dog_food_dislikes: opt.dog_food_dislikes_filename.clone(),
+            dog_ingredient_exclusions: opt.dog_ingredient_exclusions_filename.clone(),
+            ..InputFilenames::new(
+                &opt.dog_food_filename,
+                &opt.food_ingredients_filename,
+                &opt.ingredients_flavor_filename,
+            )
+        },
+        &OutputOptions {
+            shards: opt.shards,
+            manifest_filename: opt.manifest_filename.clone(),
+            format: opt.format,
+            vocabulary_filename: opt.vocabulary_filename.clone(),
+            checkpoint_filename: opt.checkpoint_filename.clone(),
+            checkpoint_every: opt.checkpoint_every,
+            resume: opt.resume,
+            ..OutputOptions::new(&opt.output_filename)
+        },
+        &WalkOptions {
+            seed: opt.seed,
+            dogs: opt.selected_dogs(),
+            start_types: opt.start_types.clone(),
+            walks_per_node: opt.walks_per_node,
+            mode: if opt.non_backtracking {
+                WalkMode::NonBacktracking
+            } else {
+                WalkMode::Simple
+            },
+            ..WalkOptions::new(opt.lines_per_dog, opt.walks_per_line)
+        },
+        &opt.csv.into_options(),
+        progress,
     );
 
-    println!("Done! Took {}ms", start.elapsed().as_millis());
+    eprintln!(
+        "Wrote {} walks from {} nodes",
+        report.lines_written, report.nodes_walked
+    );
+    if !report.unknown_dogs.is_empty() {
+        eprintln!(
+            "Skipped dogs that aren't in the graph: {}",
+            report.unknown_dogs.join(", ")
+        );
+    }
+    if let Some(format) = opt.report {
+        eprintln!("{}", report.format(format));
+    }
+    eprintln!("Done! Took {}ms", start.elapsed().as_millis());
 }
@@ -0,0 +1,308 @@
+use super::format::Vocabulary;
+use super::graph::{read_u32, Graph, Neighbors, NodeNames};
+use super::mapping::{Mapping, NodeType, Relation};
+use memmap2::Mmap;
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"DOGGRAPH";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 48;
+
+/// A read-only graph served straight from a memory-mapped file written by
+/// `MappedGraph::write`, so many processes can share one copy of a large graph and start
+/// without parsing anything.
+///
+/// The file is little-endian and every section starts on an 8 byte boundary:
+///
+/// - `DOGGRAPH`, a `u32` version and 4 bytes of padding.
+/// - The number of dogs, foods, ingredients and flavors, as `u64`s.
+/// - The names of every node, sorted by type and then by name, so a node's ID is its index in
+///   this list, just like in the vocabulary of the `ids` output format. That's `count + 1`
+///   `u64` offsets followed by the UTF-8 bytes they point into.
+/// - For each relation, in `Relation::ALL` order, `u64` offsets into its neighbor list for
+///   each node of type `relation.from()`, plus one for the end, followed by the neighbor list
+///   as `u32` node IDs.
+///
+/// `open` only checks the layout, so lookups panic if the file is corrupt or is changed while
+/// it's mapped.
+pub struct MappedGraph {
+    mmap: Mmap,
+    /// The first ID of each type, in `NodeType::ALL` order, and one past the last ID.
+    type_starts: [usize; 5],
+    name_offsets: usize,
+    name_bytes: usize,
+    /// Where each relation's offsets and neighbors start, in `Relation::ALL` order.
+    relations: [(usize, usize); 6],
+}
+
+impl MappedGraph {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // Changing the file while it's mapped is undefined behavior. Graph files are written
+        // once and then only read, like the source files they're built from.
+        let mmap = unsafe { Mmap::map(&file)? };
+        Self::from_mmap(mmap)
+    }
+
+    fn from_mmap(mmap: Mmap) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        if mmap.len() < HEADER_LEN || &mmap[..8] != MAGIC {
+            return Err(invalid("not a graph file"));
+        }
+        if read_u32(&mmap[8..12], 0) != VERSION {
+            return Err(invalid("unsupported graph file version"));
+        }
+
+        // Checks that `words` words of `size` bytes fit at `position`, returning the position
+        // after them rounded up to the next section.
+        let len = mmap.len();
+        let section = |position: usize, words: u64, size: u64| {
+            let end = words
+                .checked_mul(size)
+                .and_then(|bytes| bytes.checked_add(position as u64))
+                .filter(|&end| end <= len as u64)
+                .ok_or_else(|| invalid("truncated graph file"))?;
+            Ok::<usize, io::Error>((end.div_ceil(8) * 8) as usize)
+        };
+
+        let mut type_starts = [0usize; 5];
+        for (index, node_type) in NodeType::ALL.iter().enumerate() {
+            let count = read_u64(&mmap[16..], *node_type as usize);
+            type_starts[index + 1] = type_starts[index].saturating_add(count as usize);
+        }
+
+        let name_offsets = HEADER_LEN;
+        let name_bytes = section(name_offsets, (type_starts[4] as u64).saturating_add(1), 8)?;
+        let last_name_offset = read_u64(&mmap[name_offsets..], type_starts[4]);
+        let mut position = section(name_bytes, last_name_offset, 1)?;
+
+        let mut relations = [(0, 0); 6];
+        for (index, relation) in Relation::ALL.iter().enumerate() {
+            let from = relation.from() as usize;
+            let nodes = type_starts[from + 1] - type_starts[from];
+            let offsets = position;
+            let neighbors = section(offsets, nodes as u64 + 1, 8)?;
+            let neighbor_count = read_u64(&mmap[offsets..], nodes);
+            position = section(neighbors, neighbor_count, 4)?;
+            relations[index] = (offsets, neighbors);
+        }
+
+        Ok(MappedGraph {
+            mmap,
+            type_starts,
+            name_offsets,
+            name_bytes,
+            relations,
+        })
+    }
+
+    /// Writes `mapping` in the format `open` reads.
+    pub fn write<W: Write>(mapping: &Mapping, writer: &mut W) -> io::Result<()> {
+        let vocabulary = Vocabulary::new(mapping);
+        let tokens = vocabulary.tokens();
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&[0; 4])?;
+        for &node_type in NodeType::ALL.iter() {
+            let count = tokens
+                .iter()
+                .filter(|(other, _)| *other == node_type)
+                .count();
+            writer.write_all(&(count as u64).to_le_bytes())?;
+        }
+
+        let mut offset = 0u64;
+        writer.write_all(&offset.to_le_bytes())?;
+        for (_, name) in tokens {
+            offset += name.len() as u64;
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        for (_, name) in tokens {
+            writer.write_all(name.as_bytes())?;
+        }
+        pad(writer, offset)?;
+
+        for &relation in Relation::ALL.iter() {
+            let from_nodes: Vec<&str> = tokens
+                .iter()
+                .filter(|(node_type, _)| *node_type == relation.from())
+                .map(|(_, name)| *name)
+                .collect();
+            let neighbors = |name| mapping.neighbors(relation, name).unwrap_or(&[]);
+
+            let mut offset = 0u64;
+            writer.write_all(&offset.to_le_bytes())?;
+            for &name in &from_nodes {
+                offset += neighbors(name).len() as u64;
+                writer.write_all(&offset.to_le_bytes())?;
+            }
+            for &name in &from_nodes {
+                for neighbor in neighbors(name) {
+                    let id = vocabulary.id(relation.to(), neighbor);
+                    writer.write_all(&id.to_le_bytes())?;
+                }
+            }
+            pad(writer, offset * 4)?;
+        }
+        Ok(())
+    }
+
+    /// The name of the node with the given ID.
+    ///
+    /// # Panics
+    ///
+    /// If `id` isn't less than the number of nodes, or the graph file has a name that isn't
+    /// UTF-8, which `open` doesn't check for.
+    pub fn name(&self, id: u32) -> &str {
+        let offsets = &self.mmap[self.name_offsets..];
+        let start = read_u64(offsets, id as usize) as usize;
+        let end = read_u64(offsets, id as usize + 1) as usize;
+        let bytes = &self.mmap[self.name_bytes + start..self.name_bytes + end];
+        std::str::from_utf8(bytes).expect("graph file names should be UTF-8")
+    }
+
+    /// The index of `name` among the nodes of its type, found by binary search.
+    fn find(&self, node_type: NodeType, name: &str) -> Option<usize> {
+        let start = self.type_starts[node_type as usize];
+        let (mut low, mut high) = (start, self.type_starts[node_type as usize + 1]);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match self.name(middle as u32).cmp(name) {
+                Ordering::Less => low = middle + 1,
+                Ordering::Greater => high = middle,
+                Ordering::Equal => return Some(middle - start),
+            }
+        }
+        None
+    }
+}
+
+impl Graph for MappedGraph {
+    fn neighbor_list(&self, relation: Relation, id: &str) -> Option<Neighbors<'_>> {
+        let index = self.find(relation.from(), id)?;
+        let (offsets, neighbors) = self.relations[relation as usize];
+        let start = read_u64(&self.mmap[offsets..], index) as usize;
+        let end = read_u64(&self.mmap[offsets..], index + 1) as usize;
+        let ids = &self.mmap[neighbors + start * 4..neighbors + end * 4];
+        Some(Neighbors::numbers(self, ids))
+    }
+
+    fn node_ids(&self, node_type: NodeType) -> Box<dyn Iterator<Item = &str> + '_> {
+        let ids = self.type_starts[node_type as usize]..self.type_starts[node_type as usize + 1];
+        Box::new(ids.map(move |id| self.name(id as u32)))
+    }
+}
+
+impl NodeNames for MappedGraph {
+    fn node_name(&self, id: u32) -> &str {
+        self.name(id)
+    }
+}
+
+fn read_u64(bytes: &[u8], index: usize) -> u64 {
+    let mut word = [0; 8];
+    word.copy_from_slice(&bytes[index * 8..index * 8 + 8]);
+    u64::from_le_bytes(word)
+}
+
+/// Pads a section of `len` bytes out to the next 8 byte boundary.
+fn pad<W: Write>(writer: &mut W, len: u64) -> io::Result<()> {
+    let padding = (8 - len % 8) % 8;
+    writer.write_all(&[0; 8][..padding as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::mapping_with;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::env;
+    use std::io::BufWriter;
+
+    fn mapping() -> Mapping {
+        mapping_with("Sir Barks,taco")
+    }
+
+    fn mapped(mapping: &Mapping, filename: &str) -> MappedGraph {
+        let path = env::temp_dir().join(format!("dog_food_for_you_{}", filename));
+        let mut file = BufWriter::new(File::create(&path).unwrap());
+        MappedGraph::write(mapping, &mut file).unwrap();
+        file.flush().unwrap();
+        drop(file);
+        MappedGraph::open(&path).unwrap()
+    }
+
+    fn sorted<'a>(ids: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+        let mut ids: Vec<&str> = ids.collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn test_same_graph_as_mapping() {
+        let mapping = mapping();
+        let graph = mapped(&mapping, "same_graph.bin");
+
+        for &node_type in NodeType::ALL.iter() {
+            assert_eq!(
+                sorted(graph.node_ids(node_type)),
+                sorted(mapping.node_ids(node_type))
+            );
+        }
+        for &relation in Relation::ALL.iter() {
+            for id in mapping.node_ids(relation.from()) {
+                let expected: Vec<&str> = mapping
+                    .neighbor_list(relation, id)
+                    .unwrap()
+                    .iter()
+                    .collect();
+                let actual: Vec<&str> = graph.neighbor_list(relation, id).unwrap().iter().collect();
+                assert_eq!(actual, expected, "{:?} {}", relation, id);
+            }
+        }
+        assert!(graph.neighbor_list(Relation::DogFood, "Rex").is_none());
+        assert!(graph.neighbor_list(Relation::DogFood, "burger").is_none());
+    }
+
+    #[test]
+    fn test_samplers() {
+        let mapping = mapping();
+        let graph = mapped(&mapping, "samplers.bin");
+        let mut rng = StdRng::seed_from_u64(7);
+
+        assert_eq!(
+            graph.try_food_liked_by_dog("Sir Barks", &mut rng),
+            Some("taco")
+        );
+        assert_eq!(graph.flavor_for_ingredient("beef", &mut rng), "savory");
+        assert_eq!(graph.try_food_liked_by_dog("Rex", &mut rng), None);
+
+        let walk = crate::get_walk(3, "Sparky", &graph, &mut rng);
+        assert_eq!(walk.len(), 19);
+        assert!(["Sparky", "Max", "Sir Barks"].contains(&walk[18]));
+    }
+
+    #[test]
+    fn test_open_errors() {
+        let path = env::temp_dir().join("dog_food_for_you_not_a_graph.bin");
+        std::fs::write(
+            &path,
+            b"Sparky,burger\nMax,taco\nSir Barks,taco\nRex,kale\n",
+        )
+        .unwrap();
+        let error = MappedGraph::open(&path).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut bytes = Vec::new();
+        MappedGraph::write(&mapping(), &mut bytes).unwrap();
+        bytes.truncate(bytes.len() - 8);
+        std::fs::write(&path, &bytes).unwrap();
+        let error = MappedGraph::open(&path).err().unwrap();
+        assert_eq!(error.to_string(), "truncated graph file");
+    }
+}
@@ -1,10 +1,183 @@
-use rand::Rng;
+use super::graph::{Graph, Neighbors};
+use super::input::{read_records, CsvOptions, InputFile};
+use super::report::Report;
 use std::collections::{HashMap, HashSet};
-use std::io::BufRead;
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
 
 type Lines = Vec<Vec<String>>;
 type Map = HashMap<String, Vec<String>>;
 
+/// The kinds of node in the graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NodeType {
+    Dog,
+    Food,
+    Ingredient,
+    Flavor,
+}
+
+impl NodeType {
+    pub const ALL: [NodeType; 4] = [
+        NodeType::Dog,
+        NodeType::Food,
+        NodeType::Ingredient,
+        NodeType::Flavor,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NodeType::Dog => "dog",
+            NodeType::Food => "food",
+            NodeType::Ingredient => "ingredient",
+            NodeType::Flavor => "flavor",
+        }
+    }
+
+    pub fn plural(self) -> &'static str {
+        match self {
+            NodeType::Dog => "dogs",
+            NodeType::Food => "foods",
+            NodeType::Ingredient => "ingredients",
+            NodeType::Flavor => "flavors",
+        }
+    }
+}
+
+impl fmt::Display for NodeType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for NodeType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NodeType::ALL
+            .iter()
+            .copied()
+            .find(|node_type| node_type.as_str() == s)
+            .ok_or_else(|| format!("expected dog, food, ingredient or flavor but got {:?}", s))
+    }
+}
+
+/// Parses a comma separated list of node types, like `dog,food`, or `all` for every type.
+pub fn parse_node_types(s: &str) -> Result<Vec<NodeType>, String> {
+    if s == "all" {
+        return Ok(NodeType::ALL.to_vec());
+    }
+    let mut node_types = s
+        .split(',')
+        .map(|node_type| node_type.trim().parse())
+        .collect::<Result<Vec<NodeType>, _>>()?;
+    node_types.sort_unstable();
+    node_types.dedup();
+    Ok(node_types)
+}
+
+/// The types of the nodes in one cycle of a walk. A walk starts at a dog and repeats this
+/// cycle, ending back at a dog.
+pub const METAPATH: [NodeType; 6] = [
+    NodeType::Dog,
+    NodeType::Food,
+    NodeType::Ingredient,
+    NodeType::Flavor,
+    NodeType::Ingredient,
+    NodeType::Food,
+];
+
+/// `METAPATH` rotated to begin at `start`, for walks that start from a node of that type. A
+/// food walk goes food → ingredient → flavor → ingredient → food → dog and back to a food.
+pub fn metapath_from(start: NodeType) -> [NodeType; 6] {
+    let offset = METAPATH
+        .iter()
+        .position(|&node_type| node_type == start)
+        .unwrap();
+    let mut metapath = METAPATH;
+    metapath.rotate_left(offset);
+    metapath
+}
+
+/// The six maps of a `Mapping`, one for each direction of each input file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Relation {
+    DogFood,
+    FoodIngredient,
+    IngredientFlavor,
+    FlavorIngredient,
+    IngredientFood,
+    FoodDog,
+}
+
+impl Relation {
+    pub const ALL: [Relation; 6] = [
+        Relation::DogFood,
+        Relation::FoodIngredient,
+        Relation::IngredientFlavor,
+        Relation::FlavorIngredient,
+        Relation::IngredientFood,
+        Relation::FoodDog,
+    ];
+
+    /// The relation from nodes of type `from` to nodes of type `to`, if there is one.
+    pub fn between(from: NodeType, to: NodeType) -> Option<Relation> {
+        Relation::ALL
+            .iter()
+            .copied()
+            .find(|relation| relation.from() == from && relation.to() == to)
+    }
+
+    pub fn from(self) -> NodeType {
+        match self {
+            Relation::DogFood => NodeType::Dog,
+            Relation::FoodIngredient | Relation::FoodDog => NodeType::Food,
+            Relation::IngredientFlavor | Relation::IngredientFood => NodeType::Ingredient,
+            Relation::FlavorIngredient => NodeType::Flavor,
+        }
+    }
+
+    pub fn to(self) -> NodeType {
+        match self {
+            Relation::FoodDog => NodeType::Dog,
+            Relation::DogFood | Relation::IngredientFood => NodeType::Food,
+            Relation::FoodIngredient | Relation::FlavorIngredient => NodeType::Ingredient,
+            Relation::IngredientFlavor => NodeType::Flavor,
+        }
+    }
+
+    /// The same edges in the other direction.
+    pub fn reverse(self) -> Relation {
+        match self {
+            Relation::DogFood => Relation::FoodDog,
+            Relation::FoodIngredient => Relation::IngredientFood,
+            Relation::IngredientFlavor => Relation::FlavorIngredient,
+            Relation::FlavorIngredient => Relation::IngredientFlavor,
+            Relation::IngredientFood => Relation::FoodIngredient,
+            Relation::FoodDog => Relation::DogFood,
+        }
+    }
+
+    /// The relation read from `input`, from its left column to its right, or `None` for
+    /// dislikes and exclusions, which aren't edges of the graph.
+    pub fn of(input: InputFile) -> Option<Relation> {
+        match input {
+            InputFile::DogFood => Some(Relation::DogFood),
+            InputFile::FoodIngredient => Some(Relation::FoodIngredient),
+            InputFile::IngredientFlavor => Some(Relation::IngredientFlavor),
+            InputFile::DogFoodDislikes | InputFile::DogIngredientExclusions => None,
+        }
+    }
+
+    fn is_forward(self) -> bool {
+        matches!(
+            self,
+            Relation::DogFood | Relation::FoodIngredient | Relation::IngredientFlavor
+        )
+    }
+}
+
 pub struct Mapping {
     dog_food_map: Map,
     food_ingredient_map: Map,
@@ -12,118 +185,509 @@ pub struct Mapping {
     flavor_ingredient_map: Map,
     ingredient_food_map: Map,
     food_dog_map: Map,
+    /// Edges that aren't part of any full cycle, or are from a dog to a food it dislikes, by
+    /// relation. They're kept so updates can bring them back.
+    dormant: HashMap<Relation, Map>,
+    /// Foods each dog refused or is allergic to. Their edges are dormant, so walks never step
+    /// from a dog to one of these.
+    dislikes: HashMap<String, HashSet<String>>,
+    /// Ingredients each dog can't have. Walks never step from a dog to a food with one of these.
+    exclusions: HashMap<String, HashSet<String>>,
 }
 
 impl Mapping {
+    /// Reads the three source files, dropping any rows that can't be part of a full
+    /// dog → food → ingredient → flavor cycle.
+    ///
+    /// # Panics
+    ///
+    /// If a file can't be read or a row is missing a column. `validate` reports rows like that.
     pub fn new<R>(dog_food_file: R, food_ingredient_file: R, ingredient_flavor_file: R) -> Self
     where
-        R: BufRead,
+        R: Read,
     {
-        let (dog_food_lines, food_ingredient_lines, ingredient_flavor_lines) =
-            Self::get_filtered_lines(dog_food_file, food_ingredient_file, ingredient_flavor_file);
+        Self::with_options(
+            dog_food_file,
+            food_ingredient_file,
+            ingredient_flavor_file,
+            &CsvOptions::default(),
+        )
+    }
 
-        let (dog_food_map, food_dog_map) = Self::maps_from_lines(dog_food_lines);
-        let (food_ingredient_map, ingredient_food_map) =
-            Self::maps_from_lines(food_ingredient_lines);
-        let (ingredient_flavor_map, flavor_ingredient_map) =
-            Self::maps_from_lines(ingredient_flavor_lines);
+    /// Like `new` but reads the files as described by `options`.
+    ///
+    /// # Panics
+    ///
+    /// As for `new`, and also if `options` selects columns by name but a file doesn't have them.
+    pub fn with_options<R>(
+        dog_food_file: R,
+        food_ingredient_file: R,
+        ingredient_flavor_file: R,
+        options: &CsvOptions,
+    ) -> Self
+    where
+        R: Read,
+    {
+        Self::with_report(
+            dog_food_file,
+            food_ingredient_file,
+            ingredient_flavor_file,
+            options,
+            &mut Report::default(),
+        )
+    }
 
-        Mapping {
-            dog_food_map,
-            food_ingredient_map,
-            ingredient_flavor_map,
-            flavor_ingredient_map,
-            ingredient_food_map,
-            food_dog_map,
+    /// Like `with_options`, adding how long reading, filtering and building the maps took to
+    /// `report`.
+    pub(crate) fn with_report<R>(
+        dog_food_file: R,
+        food_ingredient_file: R,
+        ingredient_flavor_file: R,
+        options: &CsvOptions,
+        report: &mut Report,
+    ) -> Self
+    where
+        R: Read,
+    {
+        let mut read = |input, file| {
+            report.time(&format!("read {}", input), || {
+                Self::get_lines(input, file, options)
+            })
+        };
+        let lines = (
+            read(InputFile::DogFood, dog_food_file),
+            read(InputFile::FoodIngredient, food_ingredient_file),
+            read(InputFile::IngredientFlavor, ingredient_flavor_file),
+        );
+        let (kept, dropped) = report.time("filter", || Self::get_filtered_lines(lines));
+
+        report.time("build maps", || {
+            let (dog_food_lines, food_ingredient_lines, ingredient_flavor_lines) = kept;
+            let (dog_food_map, food_dog_map) = Self::maps_from_lines(dog_food_lines);
+            let (food_ingredient_map, ingredient_food_map) =
+                Self::maps_from_lines(food_ingredient_lines);
+            let (ingredient_flavor_map, flavor_ingredient_map) =
+                Self::maps_from_lines(ingredient_flavor_lines);
+
+            let mut dormant = HashMap::new();
+            let (dog_food_lines, food_ingredient_lines, ingredient_flavor_lines) = dropped;
+            for (relation, lines) in [
+                (Relation::DogFood, dog_food_lines),
+                (Relation::FoodIngredient, food_ingredient_lines),
+                (Relation::IngredientFlavor, ingredient_flavor_lines),
+            ] {
+                let (forward, reverse) = Self::maps_from_lines(lines);
+                dormant.insert(relation, forward);
+                dormant.insert(relation.reverse(), reverse);
+            }
+
+            Mapping {
+                dog_food_map,
+                food_ingredient_map,
+                ingredient_flavor_map,
+                flavor_ingredient_map,
+                ingredient_food_map,
+                food_dog_map,
+                dormant,
+                dislikes: HashMap::new(),
+                exclusions: HashMap::new(),
+            }
+        })
+    }
+
+    /// Reads a file of `dog,food` rows for foods dogs dislike. Dislikes don't have to be for
+    /// dogs or foods in the graph, and a food can be both liked and disliked by a dog, in which
+    /// case walks still never step from the dog to it.
+    ///
+    /// # Panics
+    ///
+    /// If the file can't be read or a row is missing a column.
+    pub fn read_dislikes<R: Read>(&mut self, file: R, options: &CsvOptions) {
+        for line in Self::get_lines(InputFile::DogFoodDislikes, file, options) {
+            self.add_dislike(&line[0], &line[1]);
+        }
+    }
+
+    /// Takes the edge from `dog` to `food` out of the walks, along with any edges that were
+    /// only part of a cycle through it. A dog that dislikes every food it likes is no longer
+    /// in the graph.
+    pub fn add_dislike(&mut self, dog: &str, food: &str) {
+        self.dislikes
+            .entry(dog.to_owned())
+            .or_default()
+            .insert(food.to_owned());
+        self.refresh(Relation::DogFood, dog, food);
+    }
+
+    /// Returns whether `dog` disliked `food`.
+    pub fn remove_dislike(&mut self, dog: &str, food: &str) -> bool {
+        let foods = match self.dislikes.get_mut(dog) {
+            Some(foods) => foods,
+            None => return false,
+        };
+        let removed = foods.remove(food);
+        if foods.is_empty() {
+            self.dislikes.remove(dog);
+        }
+        if removed {
+            self.refresh(Relation::DogFood, dog, food);
+        }
+        removed
+    }
+
+    /// The foods `dog` dislikes, in no particular order.
+    pub fn disliked_foods(&self, dog: &str) -> impl Iterator<Item = &String> {
+        self.dislikes.get(dog).into_iter().flatten()
+    }
+
+    /// Reads a file of `dog,ingredient` rows for ingredients dogs can't have, like allergies.
+    /// Like dislikes, exclusions don't have to be for dogs or ingredients in the graph.
+    ///
+    /// # Panics
+    ///
+    /// If the file can't be read or a row is missing a column.
+    pub fn read_exclusions<R: Read>(&mut self, file: R, options: &CsvOptions) {
+        for line in Self::get_lines(InputFile::DogIngredientExclusions, file, options) {
+            self.add_exclusion(&line[0], &line[1]);
+        }
+    }
+
+    pub fn add_exclusion(&mut self, dog: &str, ingredient: &str) {
+        self.exclusions
+            .entry(dog.to_owned())
+            .or_default()
+            .insert(ingredient.to_owned());
+    }
+
+    /// Returns whether `ingredient` was excluded for `dog`.
+    pub fn remove_exclusion(&mut self, dog: &str, ingredient: &str) -> bool {
+        let ingredients = match self.exclusions.get_mut(dog) {
+            Some(ingredients) => ingredients,
+            None => return false,
+        };
+        let removed = ingredients.remove(ingredient);
+        if ingredients.is_empty() {
+            self.exclusions.remove(dog);
+        }
+        removed
+    }
+
+    /// The ingredients excluded for `dog`, in no particular order.
+    pub fn excluded_ingredients(&self, dog: &str) -> impl Iterator<Item = &String> {
+        self.exclusions.get(dog).into_iter().flatten()
+    }
+
+    /// Reads a delta file with `Delta::read` and applies it. Returns how many rows were
+    /// applied. Nothing is applied if any row can't be read or is malformed.
+    pub fn apply_delta<R: Read>(
+        &mut self,
+        input: InputFile,
+        file: R,
+        options: &CsvOptions,
+    ) -> Result<usize, String> {
+        let delta = Delta::read(input, file, options)?;
+        Ok(self.apply(&delta))
+    }
+
+    /// Applies every row of `delta` with `add_edge` and `remove_edge`, or the `_dislike` and
+    /// `_exclusion` methods for the dislikes and exclusions files. Returns how many rows were
+    /// applied.
+    pub fn apply(&mut self, delta: &Delta) -> usize {
+        let input = delta.input;
+        for (added, left, right) in &delta.changes {
+            match input {
+                InputFile::DogFood | InputFile::FoodIngredient | InputFile::IngredientFlavor => {
+                    let relation = Relation::of(input).expect("source files have a relation");
+                    if *added {
+                        self.add_edge(relation, left, right);
+                    } else {
+                        self.remove_edge(relation, left, right);
+                    }
+                }
+                InputFile::DogFoodDislikes => {
+                    if *added {
+                        self.add_dislike(left, right);
+                    } else {
+                        self.remove_dislike(left, right);
+                    }
+                }
+                InputFile::DogIngredientExclusions => {
+                    if *added {
+                        self.add_exclusion(left, right);
+                    } else {
+                        self.remove_exclusion(left, right);
+                    }
+                }
+            }
+        }
+        delta.changes.len()
+    }
+
+    /// Adds an edge, given in either direction of `relation`. The edge, and any edges from the
+    /// input or earlier updates that it completes a cycle for, become part of the mapping as
+    /// soon as they're part of a full dog → food → ingredient → flavor cycle, just like `new`
+    /// would have kept them.
+    pub fn add_edge(&mut self, relation: Relation, from: &str, to: &str) {
+        let (relation, from, to) = forward(relation, from, to);
+        self.insert(false, relation, from, to);
+        self.refresh(relation, from, to);
+    }
+
+    /// Removes every copy of an edge, given in either direction of `relation`, along with any
+    /// edges that are no longer part of a full cycle without it. Returns whether there was such
+    /// an edge.
+    pub fn remove_edge(&mut self, relation: Relation, from: &str, to: &str) -> bool {
+        let (relation, from, to) = forward(relation, from, to);
+        let removed = self.take(relation, from, to) > 0;
+        if removed {
+            self.refresh(relation, from, to);
         }
+        removed
     }
 
     pub fn dogs(&self) -> impl Iterator<Item = &String> {
         self.dog_food_map.keys()
     }
 
-    pub fn food_liked_by_dog<R: Rng>(&self, dog: &str, rng: &mut R) -> &str {
-        let food_list = &self.dog_food_map[dog];
-        &food_list[rng.gen_range(0, food_list.len())]
+    /// Every node of the given type, in no particular order.
+    pub fn nodes(&self, node_type: NodeType) -> impl Iterator<Item = &String> {
+        match node_type {
+            NodeType::Dog => self.dog_food_map.keys(),
+            NodeType::Food => self.food_ingredient_map.keys(),
+            NodeType::Ingredient => self.ingredient_flavor_map.keys(),
+            NodeType::Flavor => self.flavor_ingredient_map.keys(),
+        }
+    }
+
+    /// The nodes `id` is related to, or `None` if `id` isn't a node of type `relation.from()`.
+    /// Lists can contain duplicates if the input files did.
+    pub fn neighbors(&self, relation: Relation, id: &str) -> Option<&[String]> {
+        self.map(relation).get(id).map(Vec::as_slice)
+    }
+
+    fn map_mut(&mut self, live: bool, relation: Relation) -> &mut Map {
+        if !live {
+            return self.dormant.entry(relation).or_default();
+        }
+        match relation {
+            Relation::DogFood => &mut self.dog_food_map,
+            Relation::FoodIngredient => &mut self.food_ingredient_map,
+            Relation::IngredientFlavor => &mut self.ingredient_flavor_map,
+            Relation::FlavorIngredient => &mut self.flavor_ingredient_map,
+            Relation::IngredientFood => &mut self.ingredient_food_map,
+            Relation::FoodDog => &mut self.food_dog_map,
+        }
+    }
+
+    /// Every edge from `id` read or added, whether or not it's part of a cycle.
+    fn all_neighbors(&self, relation: Relation, id: &str) -> impl Iterator<Item = &str> {
+        let live = self.map(relation).get(id);
+        let dormant = self.dormant.get(&relation).and_then(|map| map.get(id));
+        live.into_iter()
+            .chain(dormant)
+            .flatten()
+            .map(String::as_str)
+    }
+
+    /// Whether `id` has any edges, not counting ones between a dog and a food it dislikes.
+    fn has_any_neighbors(&self, relation: Relation, id: &str) -> bool {
+        self.all_neighbors(relation, id)
+            .any(|neighbor| match relation {
+                Relation::DogFood => !self.dislikes(id, neighbor),
+                Relation::FoodDog => !self.dislikes(neighbor, id),
+                _ => true,
+            })
+    }
+
+    /// Whether a forward edge is part of a full cycle and not from a dog to a food it
+    /// dislikes. Without dislikes this is the same rule `get_filtered_lines` applies, and
+    /// either way it only depends on the edges around it.
+    fn is_live(&self, relation: Relation, from: &str, to: &str) -> bool {
+        match relation {
+            Relation::DogFood => {
+                !self.dislikes(from, to)
+                    && self
+                        .all_neighbors(Relation::FoodIngredient, to)
+                        .any(|ingredient| {
+                            self.has_any_neighbors(Relation::IngredientFlavor, ingredient)
+                        })
+            }
+            Relation::FoodIngredient => {
+                self.has_any_neighbors(Relation::FoodDog, from)
+                    && self.has_any_neighbors(Relation::IngredientFlavor, to)
+            }
+            Relation::IngredientFlavor => self
+                .all_neighbors(Relation::IngredientFood, from)
+                .any(|food| self.has_any_neighbors(Relation::FoodDog, food)),
+            _ => unreachable!("{:?} isn't a forward relation", relation),
+        }
+    }
+
+    /// Re-checks every forward edge whose place in a cycle could depend on `from → to`,
+    /// moving it between the live and dormant maps.
+    fn refresh(&mut self, relation: Relation, from: &str, to: &str) {
+        let (foods, ingredients): (Vec<&str>, Vec<&str>) = match relation {
+            Relation::DogFood => (
+                vec![to],
+                self.all_neighbors(Relation::FoodIngredient, to).collect(),
+            ),
+            Relation::FoodIngredient => (vec![from], vec![to]),
+            Relation::IngredientFlavor => (
+                self.all_neighbors(Relation::IngredientFood, from).collect(),
+                vec![from],
+            ),
+            _ => unreachable!("{:?} isn't a forward relation", relation),
+        };
+
+        let mut edges: Vec<(Relation, String, String)> = Vec::new();
+        let mut add =
+            |relation, from: &str, to: &str| edges.push((relation, from.to_owned(), to.to_owned()));
+        for &food in &foods {
+            for dog in self.all_neighbors(Relation::FoodDog, food) {
+                add(Relation::DogFood, dog, food);
+            }
+            for ingredient in self.all_neighbors(Relation::FoodIngredient, food) {
+                add(Relation::FoodIngredient, food, ingredient);
+            }
+        }
+        for &ingredient in &ingredients {
+            for food in self.all_neighbors(Relation::IngredientFood, ingredient) {
+                add(Relation::FoodIngredient, food, ingredient);
+            }
+            for flavor in self.all_neighbors(Relation::IngredientFlavor, ingredient) {
+                add(Relation::IngredientFlavor, ingredient, flavor);
+            }
+        }
+        edges.sort_unstable();
+        edges.dedup();
+
+        for (relation, from, to) in edges {
+            // Only move the copies on the wrong side, so lists keep their order.
+            let live = self.is_live(relation, &from, &to);
+            for _ in 0..self.take_from(!live, relation, &from, &to) {
+                self.insert(live, relation, &from, &to);
+            }
+        }
+    }
+
+    /// Removes every copy of a forward edge, live or dormant, returning how many there were.
+    fn take(&mut self, relation: Relation, from: &str, to: &str) -> usize {
+        self.take_from(true, relation, from, to) + self.take_from(false, relation, from, to)
+    }
+
+    fn take_from(&mut self, live: bool, relation: Relation, from: &str, to: &str) -> usize {
+        remove_all(self.map_mut(live, relation.reverse()), to, from);
+        remove_all(self.map_mut(live, relation), from, to)
+    }
+
+    fn insert(&mut self, live: bool, relation: Relation, from: &str, to: &str) {
+        let push = |map: &mut Map, from: &str, to: &str| {
+            map.entry(from.to_owned()).or_default().push(to.to_owned())
+        };
+        push(self.map_mut(live, relation), from, to);
+        push(self.map_mut(live, relation.reverse()), to, from);
+    }
+
+    fn map(&self, relation: Relation) -> &Map {
+        match relation {
+            Relation::DogFood => &self.dog_food_map,
+            Relation::FoodIngredient => &self.food_ingredient_map,
+            Relation::IngredientFlavor => &self.ingredient_flavor_map,
+            Relation::FlavorIngredient => &self.flavor_ingredient_map,
+            Relation::IngredientFood => &self.ingredient_food_map,
+            Relation::FoodDog => &self.food_dog_map,
+        }
+    }
+
+    /// The foods `dog` likes, or `None` if it isn't in the mapping.
+    pub fn foods_liked_by_dog(&self, dog: &str) -> Option<&[String]> {
+        self.neighbors(Relation::DogFood, dog)
     }
 
-    pub fn ingredient_in_food<R: Rng>(&self, food: &str, rng: &mut R) -> &str {
-        let ingredient_list = &self.food_ingredient_map[food];
-        &ingredient_list[rng.gen_range(0, ingredient_list.len())]
+    pub fn ingredients_in_food(&self, food: &str) -> Option<&[String]> {
+        self.neighbors(Relation::FoodIngredient, food)
     }
 
-    pub fn flavor_for_ingredient<R: Rng>(&self, ingredient: &str, rng: &mut R) -> &str {
-        let flavor_list = &self.ingredient_flavor_map[ingredient];
-        &flavor_list[rng.gen_range(0, flavor_list.len())]
+    pub fn flavors_for_ingredient(&self, ingredient: &str) -> Option<&[String]> {
+        self.neighbors(Relation::IngredientFlavor, ingredient)
     }
 
-    pub fn ingredient_with_flavor<R: Rng>(&self, flavor: &str, rng: &mut R) -> &str {
-        let ingredient_list = &self.flavor_ingredient_map[flavor];
-        &ingredient_list[rng.gen_range(0, ingredient_list.len())]
+    pub fn ingredients_with_flavor(&self, flavor: &str) -> Option<&[String]> {
+        self.neighbors(Relation::FlavorIngredient, flavor)
     }
 
-    pub fn food_with_ingredient<R: Rng>(&self, ingredient: &str, rng: &mut R) -> &str {
-        let food_list = &self.ingredient_food_map[ingredient];
-        &food_list[rng.gen_range(0, food_list.len())]
+    pub fn foods_with_ingredient(&self, ingredient: &str) -> Option<&[String]> {
+        self.neighbors(Relation::IngredientFood, ingredient)
     }
 
-    pub fn dog_that_likes_food<R: Rng>(&self, food: &str, rng: &mut R) -> &str {
-        let dog_list = &self.food_dog_map[food];
-        &dog_list[rng.gen_range(0, dog_list.len())]
+    pub fn dogs_that_like_food(&self, food: &str) -> Option<&[String]> {
+        self.neighbors(Relation::FoodDog, food)
     }
 
-    fn get_lines<R: BufRead>(file: R) -> Lines {
-        file.lines()
-            .map(|line| line.unwrap())
-            .map(|line| line.split(',').map(String::from).collect())
+    fn get_lines<R: Read>(input: InputFile, file: R, options: &CsvOptions) -> Lines {
+        read_records(input, file, options)
+            .map(|record| record.unwrap_or_else(|error| panic!("{}", error)))
+            .map(|record| match record.ids() {
+                Some((left, right)) => vec![left.to_owned(), right.to_owned()],
+                None => panic!(
+                    "{} line {} is missing a column: {}",
+                    input,
+                    record.line_number(),
+                    record.line()
+                ),
+            })
             .collect()
     }
 
-    fn get_filtered_lines<R: BufRead>(
-        dog_food_file: R,
-        food_ingredient_file: R,
-        ingredient_flavor_file: R,
-    ) -> (Lines, Lines, Lines) {
-        let dog_food_lines = Self::get_lines(dog_food_file);
+    /// Splits the lines of each file into the ones that are part of a full cycle and the ones
+    /// that aren't.
+    fn get_filtered_lines(
+        (dog_food_lines, food_ingredient_lines, ingredient_flavor_lines): (Lines, Lines, Lines),
+    ) -> ((Lines, Lines, Lines), (Lines, Lines, Lines)) {
         let foods: HashSet<&str> = dog_food_lines.iter().map(|ids| ids[1].as_str()).collect();
 
-        let food_ingredient_lines: Vec<Vec<String>> = Self::get_lines(food_ingredient_file)
-            .into_iter()
-            .filter(|ids| foods.contains(ids[0].as_str()))
-            .collect();
+        let (food_ingredient_lines, mut dropped_food_ingredient_lines): (Lines, Lines) =
+            food_ingredient_lines
+                .into_iter()
+                .partition(|ids| foods.contains(ids[0].as_str()));
         let ingredients: HashSet<&str> = food_ingredient_lines
             .iter()
             .map(|ids| ids[1].as_str())
             .collect();
 
-        let ingredient_flavor_lines: Vec<Vec<String>> = Self::get_lines(ingredient_flavor_file)
-            .into_iter()
-            .filter(|ids| ingredients.contains(ids[0].as_str()))
-            .collect();
+        let (ingredient_flavor_lines, dropped_ingredient_flavor_lines): (Lines, Lines) =
+            ingredient_flavor_lines
+                .into_iter()
+                .partition(|ids| ingredients.contains(ids[0].as_str()));
         let ingredients: HashSet<&str> = ingredient_flavor_lines
             .iter()
             .map(|ids| ids[0].as_str())
             .collect();
 
-        let food_ingredient_lines: Vec<Vec<String>> = food_ingredient_lines
+        let (food_ingredient_lines, dropped): (Lines, Lines) = food_ingredient_lines
             .into_iter()
-            .filter(|ids| ingredients.contains(ids[1].as_str()))
-            .map(|ids| ids.into_iter().map(String::from).collect())
-            .collect();
+            .partition(|ids| ingredients.contains(ids[1].as_str()));
+        dropped_food_ingredient_lines.extend(dropped);
         let foods: HashSet<&str> = food_ingredient_lines
             .iter()
             .map(|ids| ids[0].as_str())
             .collect();
 
-        let dog_food_lines: Vec<Vec<String>> = dog_food_lines
+        let (dog_food_lines, dropped_dog_food_lines): (Lines, Lines) = dog_food_lines
             .into_iter()
-            .filter(|ids| foods.contains(ids[1].as_str()))
-            .collect();
+            .partition(|ids| foods.contains(ids[1].as_str()));
 
         (
-            dog_food_lines,
-            food_ingredient_lines,
-            ingredient_flavor_lines,
+            (
+                dog_food_lines,
+                food_ingredient_lines,
+                ingredient_flavor_lines,
+            ),
+            (
+                dropped_dog_food_lines,
+                dropped_food_ingredient_lines,
+                dropped_ingredient_flavor_lines,
+            ),
         )
     }
 
@@ -134,12 +698,12 @@ impl Mapping {
         for ids in lines {
             left_to_right_map
                 .entry(ids[0].to_owned())
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(ids[1].to_owned());
 
             right_to_left_map
                 .entry(ids[1].to_owned())
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(ids[0].to_owned());
         }
 
@@ -147,9 +711,113 @@ impl Mapping {
     }
 }
 
+/// The rows of a delta file for one input, each adding or removing an edge, dislike or
+/// exclusion. Reading one is separate from applying it, so a bad file can be rejected without
+/// touching a `Mapping`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Delta {
+    input: InputFile,
+    /// Whether each row adds or removes, and its two IDs.
+    changes: Vec<(bool, String, String)>,
+}
+
+impl Delta {
+    /// Reads a delta file for `input`, where each row is an edge prefixed with `+` to add it or
+    /// `-` to remove it, like `+Sparky,taco`. Errors on the first row that can't be read or
+    /// doesn't have a sign and both columns.
+    pub fn read<R: Read>(input: InputFile, file: R, options: &CsvOptions) -> Result<Self, String> {
+        let mut changes = Vec::new();
+        for record in read_records(input, file, options) {
+            let record = record.map_err(|error| error.to_string())?;
+            match record.delta() {
+                Some((added, left, right)) => {
+                    changes.push((added, left.to_owned(), right.to_owned()))
+                }
+                None => {
+                    return Err(format!(
+                        "{} line {} should be an edge with a + or - in front: {}",
+                        input,
+                        record.line_number(),
+                        record.line()
+                    ))
+                }
+            }
+        }
+        Ok(Delta { input, changes })
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl Graph for Mapping {
+    fn neighbor_list(&self, relation: Relation, id: &str) -> Option<Neighbors<'_>> {
+        self.neighbors(relation, id).map(Neighbors::names)
+    }
+
+    fn node_ids(&self, node_type: NodeType) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(self.nodes(node_type).map(String::as_str))
+    }
+
+    fn dislikes(&self, dog: &str, food: &str) -> bool {
+        self.dislikes
+            .get(dog)
+            .is_some_and(|foods| foods.contains(food))
+    }
+
+    fn disliked_edges(&self, dog: &str) -> usize {
+        if !self.dislikes.contains_key(dog) {
+            return 0;
+        }
+        self.dormant
+            .get(&Relation::DogFood)
+            .and_then(|map| map.get(dog))
+            .map_or(0, |foods| {
+                foods.iter().filter(|food| self.dislikes(dog, food)).count()
+            })
+    }
+
+    fn excludes(&self, dog: &str, food: &str) -> bool {
+        match self.exclusions.get(dog) {
+            Some(excluded) => self.any_ingredient(food, |ingredient| excluded.contains(ingredient)),
+            None => false,
+        }
+    }
+}
+
+/// The edge in the direction it's read from the input files.
+fn forward<'a>(relation: Relation, from: &'a str, to: &'a str) -> (Relation, &'a str, &'a str) {
+    if relation.is_forward() {
+        (relation, from, to)
+    } else {
+        (relation.reverse(), to, from)
+    }
+}
+
+fn remove_all(map: &mut Map, from: &str, to: &str) -> usize {
+    let ids = match map.get_mut(from) {
+        Some(ids) => ids,
+        None => return 0,
+    };
+    let before = ids.len();
+    ids.retain(|id| id != to);
+    let removed = before - ids.len();
+    if ids.is_empty() {
+        map.remove(from);
+    }
+    removed
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fixtures::mapping;
+    use proptest::prelude::*;
     use std::io::BufReader;
 
     /// Builds a Map (HashMap<String, Vec<String>) from something like:
@@ -162,10 +830,7 @@ mod tests {
             {
                 let mut map: Map = HashMap::new();
                 $(
-                    let mut value: Vec<String> = Vec::new();
-                    $(
-                        value.push(String::from($val));
-                    )*
+                    let value: Vec<String> = vec![$(String::from($val)),*];
                     map.insert(String::from($key), value);
                 )*
 
@@ -390,4 +1055,393 @@ mod tests {
         let expected_flavor_ingredient_map = map! { "salty" => ["cheese"] };
         assert_eq!(maps.flavor_ingredient_map, expected_flavor_ingredient_map);
     }
+
+    #[test]
+    fn test_try_samplers() {
+        let maps = mapping();
+        let mut rng = rand::thread_rng();
+
+        let food = maps.try_food_liked_by_dog("Sparky", &mut rng).unwrap();
+        assert!(food == "burger" || food == "pizza");
+        assert_eq!(maps.try_ingredient_in_food("taco", &mut rng), Some("beef"));
+        assert_eq!(
+            maps.try_flavor_for_ingredient("beef", &mut rng),
+            Some("savory")
+        );
+        assert_eq!(
+            maps.try_dog_that_likes_food("pizza", &mut rng),
+            Some("Sparky")
+        );
+
+        assert_eq!(maps.try_food_liked_by_dog("Rex", &mut rng), None);
+        assert_eq!(maps.try_ingredient_with_flavor("sweet", &mut rng), None);
+        // IDs are only looked up among nodes of the relation's type.
+        assert_eq!(maps.try_food_with_ingredient("burger", &mut rng), None);
+    }
+
+    #[test]
+    fn test_neighbor_accessors() {
+        let mut maps = Mapping::new(
+            "Sparky,burger\nMax,burger".as_bytes(),
+            "burger,cheese".as_bytes(),
+            "cheese,salty".as_bytes(),
+        );
+
+        let dogs: &[String] = &[String::from("Sparky"), String::from("Max")];
+        assert_eq!(maps.dogs_that_like_food("burger"), Some(dogs));
+        assert_eq!(
+            maps.ingredients_in_food("burger").map(<[String]>::len),
+            Some(1)
+        );
+        assert_eq!(maps.foods_liked_by_dog("Rex"), None);
+
+        // Not something `new` builds, but sampling still shouldn't panic.
+        maps.dog_food_map.insert(String::from("Rex"), Vec::new());
+        assert_eq!(maps.foods_liked_by_dog("Rex"), Some(&[][..]));
+        assert_eq!(
+            maps.try_food_liked_by_dog("Rex", &mut rand::thread_rng()),
+            None
+        );
+    }
+
+    /// Every live map, with each list sorted so update order doesn't matter.
+    fn sorted_maps(maps: &Mapping) -> Vec<Vec<(String, Vec<String>)>> {
+        Relation::ALL
+            .iter()
+            .map(|&relation| {
+                let mut map: Vec<(String, Vec<String>)> = maps
+                    .map(relation)
+                    .iter()
+                    .map(|(id, ids)| {
+                        let mut ids = ids.clone();
+                        ids.sort();
+                        (id.clone(), ids)
+                    })
+                    .collect();
+                map.sort();
+                map
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_add_edge_completes_cycles() {
+        // Nobody likes pizza yet, so it and cheese are dropped.
+        let mut maps = Mapping::new(
+            "Sparky,burger".as_bytes(),
+            "burger,tomato\npizza,cheese".as_bytes(),
+            "tomato,savory\ncheese,salty".as_bytes(),
+        );
+        assert_eq!(maps.food_ingredient_map, map! { "burger" => ["tomato"] });
+        assert_eq!(maps.ingredient_flavor_map, map! { "tomato" => ["savory"] });
+
+        maps.add_edge(Relation::DogFood, "Max", "pizza");
+        assert_eq!(
+            maps.dog_food_map,
+            map! { "Sparky" => ["burger"], "Max" => ["pizza"] }
+        );
+        assert_eq!(
+            maps.food_ingredient_map,
+            map! { "burger" => ["tomato"], "pizza" => ["cheese"] }
+        );
+        assert_eq!(
+            maps.flavor_ingredient_map,
+            map! { "savory" => ["tomato"], "salty" => ["cheese"] }
+        );
+
+        // An ingredient without a flavor waits until it gets one. Reverse relations work too.
+        maps.add_edge(Relation::IngredientFood, "beef", "burger");
+        assert_eq!(maps.food_ingredient_map["burger"], vec!["tomato"]);
+        maps.add_edge(Relation::IngredientFlavor, "beef", "savory");
+        assert_eq!(maps.food_ingredient_map["burger"], vec!["tomato", "beef"]);
+        assert_eq!(maps.ingredient_food_map["beef"], vec!["burger"]);
+    }
+
+    #[test]
+    fn test_remove_edge_prunes() {
+        let mut maps = mapping();
+        let original = sorted_maps(&maps);
+
+        assert!(maps.remove_edge(Relation::DogFood, "Max", "taco"));
+        assert!(!maps.remove_edge(Relation::DogFood, "Max", "taco"));
+        assert_eq!(maps.food_dog_map.get("taco"), None);
+        assert_eq!(maps.food_ingredient_map.get("taco"), None);
+        assert_eq!(maps.ingredient_flavor_map.get("beef"), None);
+        assert_eq!(maps.flavor_ingredient_map["savory"], vec!["tomato"]);
+
+        assert!(maps.remove_edge(Relation::FlavorIngredient, "salty", "cheese"));
+        assert_eq!(
+            maps.dog_food_map,
+            map! { "Sparky" => ["burger"], "Max" => ["burger"] }
+        );
+        assert_eq!(maps.food_ingredient_map, map! { "burger" => ["tomato"] });
+
+        maps.add_edge(Relation::IngredientFlavor, "cheese", "salty");
+        maps.add_edge(Relation::DogFood, "Max", "taco");
+        assert_eq!(sorted_maps(&maps), original);
+    }
+
+    #[test]
+    fn test_add_edge_matches_new() {
+        let dog_food = "Sparky,burger\nSparky,pizza\nMax,burger\nRex,kale";
+        let food_ingredient = "burger,cheese\nburger,tomato\npizza,cheese\ntaco,beef";
+        let ingredient_flavor = "cheese,salty\ntomato,savory\nbeef,savory\nlettuce,fresh";
+        let expected = Mapping::new(
+            dog_food.as_bytes(),
+            food_ingredient.as_bytes(),
+            ingredient_flavor.as_bytes(),
+        );
+
+        // Flavors first, so nothing is live until the dogs show up.
+        let mut maps = Mapping::new("".as_bytes(), "".as_bytes(), "".as_bytes());
+        for (relation, file) in [
+            (Relation::IngredientFlavor, ingredient_flavor),
+            (Relation::FoodIngredient, food_ingredient),
+            (Relation::DogFood, dog_food),
+        ] {
+            for line in file.lines() {
+                let mut ids = line.split(',');
+                maps.add_edge(relation, ids.next().unwrap(), ids.next().unwrap());
+            }
+        }
+        assert_eq!(sorted_maps(&maps), sorted_maps(&expected));
+    }
+
+    #[test]
+    fn test_apply_delta() {
+        let mut maps = Mapping::new(
+            "Sparky,burger\nMax,burger".as_bytes(),
+            "burger,cheese\npizza,cheese".as_bytes(),
+            "cheese,salty".as_bytes(),
+        );
+        let options = CsvOptions::default();
+
+        let applied = maps
+            .apply_delta(
+                InputFile::DogFood,
+                "dog,food\n+Max,pizza\n-Sparky,burger\n".as_bytes(),
+                &options,
+            )
+            .unwrap();
+        assert_eq!(applied, 2);
+        assert_eq!(maps.dog_food_map, map! { "Max" => ["burger", "pizza"] });
+
+        let error = maps
+            .apply_delta(
+                InputFile::DogFood,
+                "+Sparky,pizza\nSparky,burger".as_bytes(),
+                &options,
+            )
+            .unwrap_err();
+        assert_eq!(
+            error,
+            "dog_food line 2 should be an edge with a + or - in front: Sparky,burger"
+        );
+        assert_eq!(maps.foods_liked_by_dog("Sparky"), None);
+    }
+
+    #[test]
+    fn test_metapath_from() {
+        assert_eq!(metapath_from(NodeType::Dog), METAPATH);
+        assert_eq!(
+            metapath_from(NodeType::Food),
+            [
+                NodeType::Food,
+                NodeType::Ingredient,
+                NodeType::Flavor,
+                NodeType::Ingredient,
+                NodeType::Food,
+                NodeType::Dog,
+            ]
+        );
+        assert_eq!(metapath_from(NodeType::Flavor)[0], NodeType::Flavor);
+    }
+
+    #[test]
+    fn test_parse_node_types() {
+        assert_eq!(
+            parse_node_types("food,dog,food"),
+            Ok(vec![NodeType::Dog, NodeType::Food])
+        );
+        assert_eq!(parse_node_types("all"), Ok(NodeType::ALL.to_vec()));
+        assert!(parse_node_types("dog,cat").is_err());
+    }
+
+    #[test]
+    fn test_try_sample_filtered() {
+        let mapping = Mapping::new(
+            "Sparky,burger\nSparky,pizza\nSparky,taco\n".as_bytes(),
+            "burger,beef\npizza,beef\ntaco,beef\n".as_bytes(),
+            "beef,savory\n".as_bytes(),
+        );
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let food = mapping.try_sample_filtered(
+                Relation::DogFood,
+                "Sparky",
+                |food| food != "burger",
+                &mut rng,
+            );
+            assert!(food == Some("pizza") || food == Some("taco"));
+        }
+        assert_eq!(
+            mapping.try_sample_filtered(Relation::DogFood, "Sparky", |_| false, &mut rng),
+            None
+        );
+        assert_eq!(
+            mapping.try_sample_filtered(Relation::DogFood, "Rex", |_| true, &mut rng),
+            None
+        );
+    }
+
+    /// Up to 12 rows between `left` and `right` IDs, drawn from few enough IDs that files
+    /// share some. Shrinks to fewer rows and lower numbered IDs.
+    fn rows(
+        left: &'static str,
+        left_count: usize,
+        right: &'static str,
+        right_count: usize,
+    ) -> impl Strategy<Value = Vec<(String, String)>> {
+        prop::collection::vec((0..left_count, 0..right_count), 0..12).prop_map(move |rows| {
+            rows.into_iter()
+                .map(|(l, r)| (format!("{}{}", left, l), format!("{}{}", right, r)))
+                .collect()
+        })
+    }
+
+    fn file(rows: &[(String, String)]) -> String {
+        rows.iter()
+            .map(|(left, right)| format!("{},{}\n", left, right))
+            .collect()
+    }
+
+    /// Every edge of a map, sorted, with duplicates.
+    fn edges(map: &Map, reversed: bool) -> Vec<(&str, &str)> {
+        let mut edges: Vec<(&str, &str)> = map
+            .iter()
+            .flat_map(|(from, tos)| tos.iter().map(move |to| (from.as_str(), to.as_str())))
+            .map(|(from, to)| if reversed { (to, from) } else { (from, to) })
+            .collect();
+        edges.sort_unstable();
+        edges
+    }
+
+    /// Checks that every dog can complete a full cycle, the maps are each other's inverses, and
+    /// exactly the edges that belong to a cycle are kept.
+    fn check_invariants(
+        mapping: &Mapping,
+        dog_food: &[(String, String)],
+        food_ingredient: &[(String, String)],
+        ingredient_flavor: &[(String, String)],
+    ) -> Result<(), TestCaseError> {
+        // Every dog can complete a full cycle: each step of the metapath from it, and from
+        // every node it reaches, has somewhere to go.
+        for dog in mapping.dogs() {
+            let mut frontier = vec![dog.as_str()];
+            for (position, &from) in METAPATH.iter().enumerate() {
+                let to = METAPATH[(position + 1) % METAPATH.len()];
+                let relation = Relation::between(from, to).unwrap();
+                let mut next = Vec::new();
+                for id in frontier {
+                    let neighbors = mapping.neighbors(relation, id).unwrap_or(&[]);
+                    prop_assert!(
+                        !neighbors.is_empty(),
+                        "{} {} can't step to any {}",
+                        from,
+                        id,
+                        to
+                    );
+                    next.extend(neighbors.iter().map(String::as_str));
+                }
+                next.sort_unstable();
+                next.dedup();
+                frontier = next;
+            }
+            prop_assert!(
+                frontier.contains(&dog.as_str()),
+                "{} can't get back to itself",
+                dog
+            );
+        }
+
+        // Every forward map is exactly the inverse of its reverse map.
+        for &relation in Relation::ALL.iter() {
+            prop_assert_eq!(
+                edges(mapping.map(relation), false),
+                edges(mapping.map(relation.reverse()), true),
+                "{:?} isn't the inverse of {:?}",
+                relation,
+                relation.reverse()
+            );
+        }
+
+        // No edge is dropped if both its ends are still in the graph and it isn't disliked,
+        // since it'd be part of a full cycle through them. No disliked edge is kept.
+        let inputs = [
+            (Relation::DogFood, dog_food),
+            (Relation::FoodIngredient, food_ingredient),
+            (Relation::IngredientFlavor, ingredient_flavor),
+        ];
+        for &(relation, rows) in inputs.iter() {
+            for (from, to) in rows.iter() {
+                let disliked = relation == Relation::DogFood
+                    && mapping.disliked_foods(from).any(|food| food == to);
+                let kept = mapping
+                    .neighbors(relation, from)
+                    .is_some_and(|neighbors| neighbors.contains(to));
+                let ends_kept = mapping.nodes(relation.from()).any(|id| id == from)
+                    && mapping.nodes(relation.to()).any(|id| id == to);
+                prop_assert!(
+                    kept || disliked || !ends_kept,
+                    "{} {} → {} {} was dropped",
+                    relation.from(),
+                    from,
+                    relation.to(),
+                    to
+                );
+                prop_assert!(!(kept && disliked), "disliked {} → {} was kept", from, to);
+            }
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn test_filtering_invariants(
+            dog_food in rows("dog", 4, "food", 5),
+            food_ingredient in rows("food", 5, "ingredient", 5),
+            ingredient_flavor in rows("ingredient", 5, "flavor", 4),
+        ) {
+            let mapping = Mapping::new(
+                file(&dog_food).as_bytes(),
+                file(&food_ingredient).as_bytes(),
+                file(&ingredient_flavor).as_bytes(),
+            );
+            check_invariants(&mapping, &dog_food, &food_ingredient, &ingredient_flavor)?;
+        }
+
+        #[test]
+        fn test_dislike_invariants(
+            dog_food in rows("dog", 4, "food", 5),
+            food_ingredient in rows("food", 5, "ingredient", 5),
+            ingredient_flavor in rows("ingredient", 5, "flavor", 4),
+            dislikes in rows("dog", 4, "food", 5),
+        ) {
+            let mut mapping = Mapping::new(
+                file(&dog_food).as_bytes(),
+                file(&food_ingredient).as_bytes(),
+                file(&ingredient_flavor).as_bytes(),
+            );
+            for (dog, food) in dislikes.iter() {
+                mapping.add_dislike(dog, food);
+            }
+            check_invariants(&mapping, &dog_food, &food_ingredient, &ingredient_flavor)?;
+
+            // Taking back half the dislikes restores the edges they'd dropped.
+            for (dog, food) in dislikes.iter().step_by(2) {
+                mapping.remove_dislike(dog, food);
+            }
+            check_invariants(&mapping, &dog_food, &food_ingredient, &ingredient_flavor)?;
+        }
+    }
 }
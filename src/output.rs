@@ -0,0 +1,296 @@
+use super::checkpoint::ShardState;
+use super::files::{self, Compression};
+use super::format::Format;
+use super::mapping::NodeType;
+use crc32fast::Hasher;
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Error, ErrorKind, IntoInnerError, Seek, SeekFrom, Write};
+
+/// Replaced by the shard number in `OutputOptions::filename`.
+pub const SHARD_PLACEHOLDER: &str = "{shard}";
+
+/// Where and how the walks are written.
+#[derive(Clone, Debug)]
+pub struct OutputOptions {
+    /// With more than one shard this should contain `{shard}`. If it doesn't, the shard number
+    /// is added before the extension.
+    pub filename: String,
+    pub shards: u32,
+    /// A tab separated file listing every shard with its line count and a CRC-32 of its
+    /// (uncompressed) contents.
+    pub manifest_filename: Option<String>,
+    pub format: Format,
+    /// Where the `Ids` format lists the nodes each ID stands for.
+    pub vocabulary_filename: String,
+    /// Where to record which dogs have been fully written. Only uncompressed files can be
+    /// checkpointed, since a compressed file can't be cut back to a checkpoint.
+    pub checkpoint_filename: Option<String>,
+    /// How many dogs to write between checkpoints.
+    pub checkpoint_every: usize,
+    /// Continue from the checkpoint instead of starting over, appending to the output files.
+    pub resume: bool,
+}
+
+impl OutputOptions {
+    pub fn new(filename: &str) -> Self {
+        OutputOptions {
+            filename: filename.to_owned(),
+            shards: 1,
+            manifest_filename: None,
+            format: Format::Text,
+            vocabulary_filename: String::from("vocabulary.tsv"),
+            checkpoint_filename: None,
+            checkpoint_every: 1000,
+            resume: false,
+        }
+    }
+
+    /// The file shard `shard` is written to.
+    pub fn shard_filename(&self, shard: u32) -> String {
+        if self.shards <= 1 {
+            return self.filename.clone();
+        }
+
+        let width = (self.shards - 1).to_string().len();
+        let number = format!("{:0width$}", shard, width = width);
+        if self.filename.contains(SHARD_PLACEHOLDER) {
+            return self.filename.replace(SHARD_PLACEHOLDER, &number);
+        }
+
+        let file_start = self.filename.rfind('/').map_or(0, |slash| slash + 1);
+        match self.filename[file_start..].find('.') {
+            Some(dot) => {
+                let (stem, extension) = self.filename.split_at(file_start + dot);
+                format!("{}-{}{}", stem, number, extension)
+            }
+            None => format!("{}-{}", self.filename, number),
+        }
+    }
+}
+
+/// The shard a dog's walks go to. This only depends on the dog's ID so re-runs put a dog in
+/// the same shard.
+pub fn shard_for(dog: &str, shards: u32) -> u32 {
+    (stable_hash(dog) % u64::from(shards.max(1))) as u32
+}
+
+/// 64 bit FNV-1a. Unlike `DefaultHasher` this is the same for every run and every build.
+pub(crate) fn stable_hash(id: &str) -> u64 {
+    fnv1a(id.bytes())
+}
+
+/// `stable_hash` for a start node, which picks its shard and seeds its RNG. Dogs hash just
+/// their ID, as they always have. Other types hash their type as well, since the source files
+/// often reuse IDs across tables, like dog `17` and food `17`.
+pub(crate) fn node_hash(node_type: NodeType, id: &str) -> u64 {
+    match node_type {
+        NodeType::Dog => stable_hash(id),
+        _ => fnv1a(
+            node_type
+                .as_str()
+                .bytes()
+                .chain(std::iter::once(0))
+                .chain(id.bytes()),
+        ),
+    }
+}
+
+fn fnv1a<I: Iterator<Item = u8>>(bytes: I) -> u64 {
+    bytes.fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+struct Shard {
+    filename: String,
+    writer: BufWriter<files::Output>,
+    bytes: u64,
+    lines: u64,
+    checksum: Hasher,
+}
+
+/// Writes lines to one or more shards, keeping track of what went where for the manifest.
+pub(crate) struct ShardedWriter {
+    shards: Vec<Shard>,
+    manifest_filename: Option<String>,
+}
+
+impl ShardedWriter {
+    pub fn create(options: &OutputOptions) -> io::Result<Self> {
+        let capacity = Self::capacity(options);
+        let mut shards = Vec::new();
+        for filename in Self::filenames(options)? {
+            shards.push(Shard {
+                writer: BufWriter::with_capacity(capacity, files::create(&filename)?),
+                filename,
+                bytes: 0,
+                lines: 0,
+                checksum: Hasher::new(),
+            });
+        }
+
+        Ok(ShardedWriter {
+            shards,
+            manifest_filename: options.manifest_filename.clone(),
+        })
+    }
+
+    /// Opens the shards a checkpoint recorded, cutting off anything written after it.
+    pub fn resume(options: &OutputOptions, states: &[ShardState]) -> io::Result<Self> {
+        let capacity = Self::capacity(options);
+        let filenames = Self::filenames(options)?;
+        let mut shards = Vec::new();
+        for (filename, state) in filenames.into_iter().zip(states) {
+            let mut file = OpenOptions::new().write(true).open(&filename)?;
+            if file.metadata()?.len() < state.bytes {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} is shorter than its checkpoint", filename),
+                ));
+            }
+            file.set_len(state.bytes)?;
+            file.seek(SeekFrom::End(0))?;
+            shards.push(Shard {
+                writer: BufWriter::with_capacity(capacity, files::Output::Plain(file)),
+                filename,
+                bytes: state.bytes,
+                lines: state.lines,
+                checksum: Hasher::new_with_initial_len(state.crc32, state.bytes),
+            });
+        }
+
+        Ok(ShardedWriter {
+            shards,
+            manifest_filename: options.manifest_filename.clone(),
+        })
+    }
+
+    /// Keeps the total buffer size about the same however many shards there are.
+    fn capacity(options: &OutputOptions) -> usize {
+        (4 * 1024 * 1024 / options.shards.max(1) as usize).max(64 * 1024)
+    }
+
+    fn filenames(options: &OutputOptions) -> io::Result<Vec<String>> {
+        let filenames: Vec<String> = (0..options.shards.max(1))
+            .map(|shard| options.shard_filename(shard))
+            .collect();
+        if options.checkpoint_filename.is_some() {
+            if let Some(filename) = filenames.iter().find(|filename| {
+                *filename == files::STANDARD_STREAM
+                    || Compression::from_path(filename) != Compression::None
+            }) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "{} can't be checkpointed, only uncompressed files can",
+                        filename
+                    ),
+                ));
+            }
+        }
+        Ok(filenames)
+    }
+
+    /// Writes one line of the walks from `node`, a node of type `node_type`. The line should
+    /// include its trailing newline. Dogs go to `shard_for` their ID.
+    pub fn write_line(&mut self, node_type: NodeType, node: &str, line: &[u8]) -> io::Result<()> {
+        let number = node_hash(node_type, node) % self.shards.len() as u64;
+        let shard = &mut self.shards[number as usize];
+        shard.writer.write_all(line)?;
+        shard.checksum.update(line);
+        shard.bytes += line.len() as u64;
+        shard.lines += 1;
+        Ok(())
+    }
+
+    /// Flushes every shard to its file and returns how far each has got.
+    pub fn checkpoint(&mut self) -> io::Result<Vec<ShardState>> {
+        let mut states = Vec::with_capacity(self.shards.len());
+        for shard in &mut self.shards {
+            shard.writer.flush()?;
+            states.push(ShardState {
+                filename: shard.filename.clone(),
+                bytes: shard.bytes,
+                lines: shard.lines,
+                crc32: shard.checksum.clone().finalize(),
+            });
+        }
+        Ok(states)
+    }
+
+    /// Flushes and finishes every shard, then writes the manifest if one was asked for.
+    pub fn finish(self) -> io::Result<()> {
+        let mut manifest = String::from("shard\tfile\tlines\tcrc32\n");
+        for (number, shard) in self.shards.into_iter().enumerate() {
+            shard
+                .writer
+                .into_inner()
+                .map_err(IntoInnerError::into_error)?
+                .finish()?;
+            manifest += &format!(
+                "{}\t{}\t{}\t{:08x}\n",
+                number,
+                shard.filename,
+                shard.lines,
+                shard.checksum.finalize()
+            );
+        }
+
+        if let Some(manifest_filename) = self.manifest_filename {
+            let mut manifest_file = files::create(&manifest_filename)?;
+            manifest_file.write_all(manifest.as_bytes())?;
+            manifest_file.finish()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sharded(filename: &str, shards: u32) -> OutputOptions {
+        OutputOptions {
+            shards,
+            ..OutputOptions::new(filename)
+        }
+    }
+
+    #[test]
+    fn test_shard_filename() {
+        assert_eq!(sharded("output.txt", 1).shard_filename(0), "output.txt");
+        assert_eq!(
+            sharded("walks/part-{shard}.txt.gz", 16).shard_filename(3),
+            "walks/part-03.txt.gz"
+        );
+        assert_eq!(
+            sharded("walks.v2/output.txt.gz", 4).shard_filename(3),
+            "walks.v2/output-3.txt.gz"
+        );
+        assert_eq!(sharded("output", 100).shard_filename(7), "output-07");
+    }
+
+    #[test]
+    fn test_shard_for_is_stable() {
+        // These must never change or re-runs would move dogs between shards.
+        assert_eq!(stable_hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(stable_hash("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(shard_for("Sparky", 1), 0);
+        assert_eq!(shard_for("Sparky", 3), 0);
+        assert_eq!(shard_for("Sparky", 8), 1);
+    }
+
+    #[test]
+    fn test_node_hash() {
+        // Dogs hash as they did before other start types, so seeded output doesn't change.
+        assert_eq!(node_hash(NodeType::Dog, "17"), stable_hash("17"));
+        assert_ne!(
+            node_hash(NodeType::Food, "17"),
+            node_hash(NodeType::Dog, "17")
+        );
+        assert_ne!(
+            node_hash(NodeType::Food, "17"),
+            node_hash(NodeType::Ingredient, "17")
+        );
+    }
+}
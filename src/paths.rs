@@ -0,0 +1,355 @@
+use super::graph::Graph;
+use super::mapping::{Mapping, NodeType, Relation};
+use std::collections::{HashMap, VecDeque};
+
+/// A node of a `Mapping`, identified by its type and ID.
+pub type Node<'a> = (NodeType, &'a str);
+
+/// Finds up to `limit` of the shortest paths from `from` to `to`, following any of the six
+/// relations. Returns no paths if either node isn't in the mapping or they aren't connected.
+pub fn shortest_paths<'a>(
+    mapping: &'a Mapping,
+    from: Node<'a>,
+    to: Node<'a>,
+    limit: usize,
+) -> Vec<Vec<Node<'a>>> {
+    if !contains(mapping, from) || !contains(mapping, to) {
+        return Vec::new();
+    }
+
+    // Every node's distance from `from` along with the nodes one step closer to it.
+    let mut parents: HashMap<Node, (usize, Vec<Node>)> = HashMap::new();
+    parents.insert(from, (0, Vec::new()));
+    let mut queue = VecDeque::new();
+    queue.push_back(from);
+
+    while let Some(node) = queue.pop_front() {
+        let distance = parents[&node].0;
+        if parents
+            .get(&to)
+            .is_some_and(|(to_distance, _)| distance >= *to_distance)
+        {
+            break;
+        }
+
+        for (_, neighbor) in neighbors(mapping, node) {
+            match parents.get_mut(&neighbor) {
+                Some((neighbor_distance, neighbor_parents)) => {
+                    if *neighbor_distance == distance + 1 && !neighbor_parents.contains(&node) {
+                        neighbor_parents.push(node);
+                    }
+                }
+                None => {
+                    parents.insert(neighbor, (distance + 1, vec![node]));
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+
+    let mut paths = Vec::new();
+    if parents.contains_key(&to) {
+        let mut path = vec![to];
+        collect_paths(&parents, &mut path, limit, &mut paths);
+    }
+    paths
+}
+
+/// Walks back from the last node of `path` to the start, adding every complete path.
+fn collect_paths<'a>(
+    parents: &HashMap<Node<'a>, (usize, Vec<Node<'a>>)>,
+    path: &mut Vec<Node<'a>>,
+    limit: usize,
+    paths: &mut Vec<Vec<Node<'a>>>,
+) {
+    let node = path[path.len() - 1];
+    let node_parents = &parents[&node].1;
+    if node_parents.is_empty() {
+        paths.push(path.iter().rev().copied().collect());
+        return;
+    }
+
+    for &parent in node_parents {
+        if paths.len() >= limit {
+            return;
+        }
+        path.push(parent);
+        collect_paths(parents, path, limit, paths);
+        path.pop();
+    }
+}
+
+/// Every neighbor of `node` across every relation, along with the relation.
+fn neighbors<'a>(
+    mapping: &'a Mapping,
+    (node_type, id): Node,
+) -> impl Iterator<Item = (Relation, Node<'a>)> + 'a {
+    let id = id.to_owned();
+    Relation::ALL
+        .iter()
+        .copied()
+        .filter(move |relation| relation.from() == node_type)
+        .flat_map(move |relation| {
+            mapping
+                .neighbors(relation, &id)
+                .unwrap_or(&[])
+                .iter()
+                .map(move |neighbor| (relation, (relation.to(), neighbor.as_str())))
+        })
+}
+
+fn contains(mapping: &Mapping, (node_type, id): Node) -> bool {
+    Relation::ALL
+        .iter()
+        .any(|&relation| relation.from() == node_type && mapping.neighbors(relation, id).is_some())
+}
+
+/// One dog → food → ingredient → flavor → ingredient → food path from a dog to a food it
+/// could be recommended.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExplainedPath<'a> {
+    /// A food the dog likes.
+    pub liked_food: &'a str,
+    /// An ingredient of `liked_food`.
+    pub liked_ingredient: &'a str,
+    pub flavor: &'a str,
+    /// An ingredient of the recommended food with `flavor`.
+    pub ingredient: &'a str,
+    /// How likely a walk from the dog is to follow this path in its first cycle.
+    pub probability: f64,
+}
+
+impl<'a> ExplainedPath<'a> {
+    /// What the two foods have in common: the ingredient if the path goes through the same one
+    /// on both sides, and the flavor otherwise.
+    pub fn shared(&self) -> (NodeType, &'a str) {
+        if self.liked_ingredient == self.ingredient {
+            (NodeType::Ingredient, self.ingredient)
+        } else {
+            (NodeType::Flavor, self.flavor)
+        }
+    }
+}
+
+/// Why a food would be recommended to a dog.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explanation<'a> {
+    /// How likely a walk from the dog is to reach the food at the end of its first cycle, the
+    /// sum of every path's probability.
+    pub probability: f64,
+    /// How many different paths there are, which can be more than `paths` holds.
+    pub path_count: usize,
+    /// The most probable paths, most probable first.
+    pub paths: Vec<ExplainedPath<'a>>,
+}
+
+/// Explains why `food` would be recommended to `dog` with the most probable of the
+/// dog → food → ingredient → flavor → ingredient → food paths connecting them, up to `limit`.
+/// Probabilities are for walks that pick every neighbor with equal chance, ignoring exclusions.
+/// Returns `None` if either isn't in the graph.
+pub fn explain<'a, G: Graph>(
+    graph: &'a G,
+    dog: &str,
+    food: &str,
+    limit: usize,
+) -> Option<Explanation<'a>> {
+    let liked_foods = graph.neighbor_list(Relation::DogFood, dog)?;
+    let food_ingredients = graph.neighbor_list(Relation::FoodIngredient, food)?;
+
+    // The recommended food's ingredients by flavor, with the chance of stepping from the
+    // flavor to the ingredient and then to the food.
+    let mut ingredients_by_flavor: HashMap<&str, Vec<(&str, f64)>> = HashMap::new();
+    for ingredient in food_ingredients.iter() {
+        let foods = graph
+            .neighbor_list(Relation::IngredientFood, ingredient)?
+            .len();
+        for flavor in graph
+            .neighbor_list(Relation::IngredientFlavor, ingredient)?
+            .iter()
+        {
+            let ingredients = graph
+                .neighbor_list(Relation::FlavorIngredient, flavor)?
+                .len();
+            let probability = 1.0 / (ingredients * foods) as f64;
+            ingredients_by_flavor
+                .entry(flavor)
+                .or_default()
+                .push((ingredient, probability));
+        }
+    }
+
+    // Edges can be listed more than once, which makes walks more likely to take them, so
+    // repeated paths are merged by adding up their probabilities.
+    let mut probabilities: HashMap<(&str, &str, &str, &str), f64> = HashMap::new();
+    for liked_food in liked_foods.iter() {
+        let liked_ingredients = graph.neighbor_list(Relation::FoodIngredient, liked_food)?;
+        for liked_ingredient in liked_ingredients.iter() {
+            let flavors = graph.neighbor_list(Relation::IngredientFlavor, liked_ingredient)?;
+            let probability =
+                1.0 / (liked_foods.len() * liked_ingredients.len() * flavors.len()) as f64;
+            for flavor in flavors.iter() {
+                for &(ingredient, rest) in ingredients_by_flavor.get(flavor).into_iter().flatten() {
+                    *probabilities
+                        .entry((liked_food, liked_ingredient, flavor, ingredient))
+                        .or_default() += probability * rest;
+                }
+            }
+        }
+    }
+
+    let mut paths: Vec<ExplainedPath> = probabilities
+        .into_iter()
+        .map(
+            |((liked_food, liked_ingredient, flavor, ingredient), probability)| ExplainedPath {
+                liked_food,
+                liked_ingredient,
+                flavor,
+                ingredient,
+                probability,
+            },
+        )
+        .collect();
+    paths.sort_by(|a, b| {
+        b.probability
+            .partial_cmp(&a.probability)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| {
+                let key = |path: &ExplainedPath<'a>| {
+                    (
+                        path.liked_food,
+                        path.liked_ingredient,
+                        path.flavor,
+                        path.ingredient,
+                    )
+                };
+                key(a).cmp(&key(b))
+            })
+    });
+    let probability = paths.iter().map(|path| path.probability).sum();
+    let path_count = paths.len();
+    paths.truncate(limit);
+    Some(Explanation {
+        probability,
+        path_count,
+        paths,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::mapping;
+
+    #[test]
+    fn test_shortest_paths() {
+        let mapping = mapping();
+
+        let paths = shortest_paths(
+            &mapping,
+            (NodeType::Dog, "Sparky"),
+            (NodeType::Flavor, "salty"),
+            10,
+        );
+        assert_eq!(paths.len(), 3);
+        for path in &paths {
+            assert_eq!(path.len(), 4);
+            assert_eq!(path[0], (NodeType::Dog, "Sparky"));
+            assert_eq!(path[3], (NodeType::Flavor, "salty"));
+        }
+
+        let paths = shortest_paths(
+            &mapping,
+            (NodeType::Food, "pizza"),
+            (NodeType::Food, "taco"),
+            10,
+        );
+        assert_eq!(paths.len(), 2);
+        assert!(paths.contains(&vec![
+            (NodeType::Food, "pizza"),
+            (NodeType::Dog, "Sparky"),
+            (NodeType::Food, "burger"),
+            (NodeType::Dog, "Max"),
+            (NodeType::Food, "taco"),
+        ]));
+        assert!(paths.contains(&vec![
+            (NodeType::Food, "pizza"),
+            (NodeType::Ingredient, "cheese"),
+            (NodeType::Food, "burger"),
+            (NodeType::Dog, "Max"),
+            (NodeType::Food, "taco"),
+        ]));
+    }
+
+    #[test]
+    fn test_shortest_paths_limit_and_unknown_nodes() {
+        let mapping = mapping();
+
+        let paths = shortest_paths(
+            &mapping,
+            (NodeType::Dog, "Sparky"),
+            (NodeType::Flavor, "salty"),
+            2,
+        );
+        assert_eq!(paths.len(), 2);
+
+        let paths = shortest_paths(
+            &mapping,
+            (NodeType::Dog, "Sparky"),
+            (NodeType::Dog, "Rex"),
+            10,
+        );
+        assert!(paths.is_empty());
+
+        let paths = shortest_paths(
+            &mapping,
+            (NodeType::Dog, "Sparky"),
+            (NodeType::Dog, "Sparky"),
+            10,
+        );
+        assert_eq!(paths, vec![vec![(NodeType::Dog, "Sparky")]]);
+    }
+
+    #[test]
+    fn test_explain() {
+        let mapping = mapping();
+
+        let explanation = explain(&mapping, "Sparky", "taco", 10).unwrap();
+        assert_eq!(
+            explanation,
+            Explanation {
+                probability: 1.0 / 16.0,
+                path_count: 1,
+                paths: vec![ExplainedPath {
+                    liked_food: "burger",
+                    liked_ingredient: "tomato",
+                    flavor: "savory",
+                    ingredient: "beef",
+                    probability: 1.0 / 16.0,
+                }],
+            }
+        );
+        assert_eq!(explanation.paths[0].shared(), (NodeType::Flavor, "savory"));
+
+        let explanation = explain(&mapping, "Max", "pizza", 1).unwrap();
+        assert_eq!(explanation.probability, 3.0 / 32.0);
+        assert_eq!(explanation.path_count, 2);
+        assert_eq!(explanation.paths.len(), 1);
+        assert_eq!(explanation.paths[0].probability, 1.0 / 16.0);
+        assert_eq!(
+            explanation.paths[0].shared(),
+            (NodeType::Ingredient, "cheese")
+        );
+
+        let mapping_with_repeats = Mapping::new(
+            "Sparky,burger\nSparky,burger\nMax,taco".as_bytes(),
+            "burger,beef\ntaco,beef".as_bytes(),
+            "beef,savory".as_bytes(),
+        );
+        let explanation = explain(&mapping_with_repeats, "Sparky", "taco", 10).unwrap();
+        assert_eq!(explanation.path_count, 1);
+        assert_eq!(explanation.paths[0].probability, 0.5);
+
+        assert_eq!(explain(&mapping, "Rex", "pizza", 10), None);
+        assert_eq!(explain(&mapping, "Max", "kale", 10), None);
+    }
+}
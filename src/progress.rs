@@ -0,0 +1,160 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// How far along `write_file_with_progress` is. Nodes are the ones walks start from, which
+/// are dogs unless other start types were asked for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProgressUpdate {
+    pub nodes_done: usize,
+    pub nodes_total: usize,
+    pub lines_written: u64,
+    /// Nodes and lines a resumed run started with, which don't count towards the rate.
+    pub nodes_resumed: usize,
+    pub lines_resumed: u64,
+    /// Since walking started in this run.
+    pub elapsed: Duration,
+}
+
+impl ProgressUpdate {
+    pub fn lines_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.lines_written.saturating_sub(self.lines_resumed) as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// How much longer the rest of the nodes should take at the rate so far, or `None` before
+    /// any are done in this run.
+    pub fn eta(&self) -> Option<Duration> {
+        let nodes_done = self.nodes_done.saturating_sub(self.nodes_resumed);
+        if nodes_done == 0 {
+            return None;
+        }
+        let per_node = self.elapsed.as_secs_f64() / nodes_done as f64;
+        let remaining = self.nodes_total.saturating_sub(self.nodes_done);
+        Some(Duration::from_secs_f64(per_node * remaining as f64))
+    }
+}
+
+/// Gets told how walking is going. Any `FnMut(&ProgressUpdate)` closure is one.
+pub trait Progress {
+    /// Called after each node's lines are written.
+    fn update(&mut self, update: &ProgressUpdate);
+
+    /// Called once every node is done.
+    fn finish(&mut self, _update: &ProgressUpdate) {}
+}
+
+impl<F: FnMut(&ProgressUpdate)> Progress for F {
+    fn update(&mut self, update: &ProgressUpdate) {
+        self(update)
+    }
+}
+
+/// Keeps one line on stderr up to date with nodes done, lines written, throughput and ETA.
+pub struct StderrProgress {
+    last_shown: Option<Instant>,
+}
+
+impl StderrProgress {
+    /// How often the line is redrawn.
+    const INTERVAL: Duration = Duration::from_millis(250);
+
+    pub fn new() -> Self {
+        StderrProgress { last_shown: None }
+    }
+
+    fn show(&mut self, update: &ProgressUpdate, end: &str) {
+        let eta = match update.eta() {
+            Some(eta) => format_duration(eta),
+            None => String::from("?"),
+        };
+        // Errors writing to stderr aren't worth stopping a run for.
+        let _ = write!(
+            io::stderr(),
+            "\r{}/{} nodes, {} lines, {:.0} lines/s, ETA {}\x1b[K{}",
+            update.nodes_done,
+            update.nodes_total,
+            update.lines_written,
+            update.lines_per_second(),
+            eta,
+            end
+        );
+        self.last_shown = Some(Instant::now());
+    }
+}
+
+impl Default for StderrProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Progress for StderrProgress {
+    fn update(&mut self, update: &ProgressUpdate) {
+        if self
+            .last_shown
+            .is_none_or(|last_shown| last_shown.elapsed() >= Self::INTERVAL)
+        {
+            self.show(update, "");
+        }
+    }
+
+    fn finish(&mut self, update: &ProgressUpdate) {
+        self.show(update, "\n");
+    }
+}
+
+/// Like `1:05` or `2:03:09`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates() {
+        let update = ProgressUpdate {
+            nodes_done: 10,
+            nodes_total: 40,
+            lines_written: 1000,
+            nodes_resumed: 0,
+            lines_resumed: 0,
+            elapsed: Duration::from_secs(5),
+        };
+        assert_eq!(update.lines_per_second(), 200.0);
+        assert_eq!(update.eta(), Some(Duration::from_secs(15)));
+
+        let resumed = ProgressUpdate {
+            nodes_done: 20,
+            lines_written: 2000,
+            nodes_resumed: 10,
+            lines_resumed: 1000,
+            ..update.clone()
+        };
+        assert_eq!(resumed.lines_per_second(), 200.0);
+        assert_eq!(resumed.eta(), Some(Duration::from_secs(10)));
+
+        let update = ProgressUpdate {
+            nodes_done: 0,
+            ..update
+        };
+        assert_eq!(update.eta(), None);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(65)), "1:05");
+        assert_eq!(format_duration(Duration::from_secs(7389)), "2:03:09");
+    }
+}
@@ -0,0 +1,262 @@
+use super::get_line::get_walk;
+use super::graph::Graph;
+use super::mapping::{NodeType, Relation, METAPATH};
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Read};
+
+/// A food a dog doesn't already like, with a score where higher is better.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recommendation {
+    pub food: String,
+    pub score: f64,
+}
+
+/// Vectors for the tokens of a walk output, in the text format written by word2vec and
+/// friends: an optional `count dimensions` line followed by `token v1 v2 ...` lines.
+#[derive(Debug, Default)]
+pub struct Embeddings {
+    vectors: HashMap<String, Vec<f32>>,
+}
+
+impl Embeddings {
+    pub fn load<R: Read>(file: R) -> io::Result<Self> {
+        let mut vectors = HashMap::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            let mut fields = line.split_whitespace();
+            let token = match fields.next() {
+                Some(token) => token,
+                None => continue,
+            };
+            let vector: Result<Vec<f32>, _> = fields.map(str::parse).collect();
+            let vector = vector.map_err(|error| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", index + 1, error),
+                )
+            })?;
+            // The header line is just two numbers.
+            if index == 0 && vector.len() == 1 && token.parse::<usize>().is_ok() {
+                continue;
+            }
+            vectors.insert(token.to_owned(), vector);
+        }
+
+        Ok(Embeddings { vectors })
+    }
+
+    pub fn get(&self, token: &str) -> Option<&[f32]> {
+        self.vectors.get(token).map(Vec::as_slice)
+    }
+}
+
+/// Recommends up to `k` foods `dog` doesn't already like or avoid, and without any of
+/// `excluded_ingredients`, ranked by how often they show up in `walks` walks of
+/// `walks_per_line` cycles from the dog. Returns `None` for unknown dogs.
+pub fn recommend_by_walks<G: Graph, R: Rng>(
+    mapping: &G,
+    dog: &str,
+    k: usize,
+    walks: usize,
+    walks_per_line: u8,
+    excluded_ingredients: &[String],
+    rng: &mut R,
+) -> Option<Vec<Recommendation>> {
+    let liked: HashSet<&str> = liked_foods(mapping, dog)?;
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let mut total = 0;
+    for _ in 0..walks {
+        let walk = get_walk(walks_per_line, dog, mapping, &mut *rng);
+        for (position, token) in walk.into_iter().enumerate() {
+            if METAPATH[position % METAPATH.len()] == NodeType::Food {
+                total += 1;
+                if is_candidate(mapping, dog, token, &liked, excluded_ingredients) {
+                    *counts.entry(token).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    let scores = counts
+        .into_iter()
+        .map(|(food, count)| (food, count as f64 / total as f64));
+    Some(top_k(scores, k))
+}
+
+/// Recommends up to `k` foods `dog` doesn't already like or avoid, and without any of
+/// `excluded_ingredients`, ranked by the cosine similarity of their embeddings to the dog's.
+/// Returns `None` for unknown dogs or dogs without an embedding.
+pub fn recommend_by_embeddings<G: Graph>(
+    mapping: &G,
+    embeddings: &Embeddings,
+    dog: &str,
+    k: usize,
+    excluded_ingredients: &[String],
+) -> Option<Vec<Recommendation>> {
+    let liked: HashSet<&str> = liked_foods(mapping, dog)?;
+    let dog_vector = embeddings.get(dog)?;
+
+    let scores = mapping
+        .node_ids(NodeType::Food)
+        .filter(|food| is_candidate(mapping, dog, food, &liked, excluded_ingredients))
+        .filter_map(|food| {
+            let food_vector = embeddings.get(food)?;
+            Some((food, cosine_similarity(dog_vector, food_vector)))
+        });
+    Some(top_k(scores, k))
+}
+
+fn liked_foods<'a, G: Graph>(mapping: &'a G, dog: &str) -> Option<HashSet<&'a str>> {
+    let foods = mapping.neighbor_list(Relation::DogFood, dog)?;
+    Some(foods.iter().collect())
+}
+
+/// Whether `food` can be recommended to `dog`: it's not one the dog already likes, dislikes
+/// or can't have, and it has none of `excluded_ingredients`.
+fn is_candidate<G: Graph>(
+    mapping: &G,
+    dog: &str,
+    food: &str,
+    liked: &HashSet<&str>,
+    excluded_ingredients: &[String],
+) -> bool {
+    !liked.contains(food)
+        && !mapping.avoids(dog, food)
+        && !mapping.any_ingredient(food, |ingredient| {
+            excluded_ingredients
+                .iter()
+                .any(|excluded| excluded == ingredient)
+        })
+}
+
+/// Sorts by descending score, breaking ties by name so results are stable.
+fn top_k<'a, I>(scores: I, k: usize) -> Vec<Recommendation>
+where
+    I: Iterator<Item = (&'a str, f64)>,
+{
+    let mut scores: Vec<(&str, f64)> = scores.collect();
+    scores.sort_by(|(food_a, score_a), (food_b, score_b)| {
+        score_b
+            .partial_cmp(score_a)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| food_a.cmp(food_b))
+    });
+    scores
+        .into_iter()
+        .take(k)
+        .map(|(food, score)| Recommendation {
+            food: food.to_owned(),
+            score,
+        })
+        .collect()
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
+    let dot: f64 = a
+        .iter()
+        .zip(b)
+        .map(|(x, y)| f64::from(*x) * f64::from(*y))
+        .sum();
+    let norm_a: f64 = a.iter().map(|x| f64::from(*x).powi(2)).sum::<f64>().sqrt();
+    let norm_b: f64 = b.iter().map(|x| f64::from(*x).powi(2)).sum::<f64>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::mapping;
+    use crate::input::CsvOptions;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn foods(recommendations: &[Recommendation]) -> Vec<&str> {
+        recommendations
+            .iter()
+            .map(|recommendation| recommendation.food.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_recommend_by_walks() {
+        let mapping = mapping();
+        let mut rng = StdRng::seed_from_u64(7);
+
+        let recommendations =
+            recommend_by_walks(&mapping, "Sparky", 5, 50, 4, &[], &mut rng).unwrap();
+        assert_eq!(foods(&recommendations), vec!["taco"]);
+
+        assert_eq!(
+            recommend_by_walks(&mapping, "Rex", 5, 50, 4, &[], &mut rng),
+            None
+        );
+    }
+
+    #[test]
+    fn test_recommend_by_embeddings() {
+        let mapping = mapping();
+        let embeddings = Embeddings::load(
+            "4 2\nSparky 1 0\nMax 0 1\nburger 1 1\ntaco 0 1\npizza 1 0".as_bytes(),
+        )
+        .unwrap();
+
+        let recommendations =
+            recommend_by_embeddings(&mapping, &embeddings, "Max", 5, &[]).unwrap();
+        assert_eq!(foods(&recommendations), vec!["pizza"]);
+        assert_eq!(recommendations[0].score, 0.0);
+
+        let recommendations =
+            recommend_by_embeddings(&mapping, &embeddings, "Sparky", 5, &[]).unwrap();
+        assert_eq!(foods(&recommendations), vec!["taco"]);
+    }
+
+    #[test]
+    fn test_recommendations_skip_dislikes() {
+        let mut mapping = mapping();
+        mapping.read_dislikes("Sparky,taco".as_bytes(), &CsvOptions::default());
+        let mut rng = StdRng::seed_from_u64(7);
+
+        let recommendations =
+            recommend_by_walks(&mapping, "Sparky", 5, 50, 4, &[], &mut rng).unwrap();
+        assert!(recommendations.is_empty());
+
+        let embeddings = Embeddings::load("2 2\nSparky 1 0\ntaco 0 1".as_bytes()).unwrap();
+        let recommendations =
+            recommend_by_embeddings(&mapping, &embeddings, "Sparky", 5, &[]).unwrap();
+        assert!(recommendations.is_empty());
+    }
+
+    #[test]
+    fn test_recommendations_skip_excluded_ingredients() {
+        let mapping = mapping();
+        let mut rng = StdRng::seed_from_u64(7);
+        let no_beef = [String::from("beef")];
+
+        let recommendations =
+            recommend_by_walks(&mapping, "Sparky", 5, 50, 4, &no_beef, &mut rng).unwrap();
+        assert!(recommendations.is_empty());
+
+        let embeddings =
+            Embeddings::load("4 2\nMax 0 1\nburger 1 1\ntaco 0 1\npizza 1 0".as_bytes()).unwrap();
+        let no_cheese = [String::from("cheese")];
+        let recommendations =
+            recommend_by_embeddings(&mapping, &embeddings, "Max", 5, &no_cheese).unwrap();
+        assert!(recommendations.is_empty());
+
+        let mut mapping = mapping;
+        mapping.add_exclusion("Sparky", "beef");
+        let recommendations =
+            recommend_by_walks(&mapping, "Sparky", 5, 50, 4, &[], &mut rng).unwrap();
+        assert!(recommendations.is_empty());
+        mapping.remove_exclusion("Sparky", "beef");
+        let recommendations =
+            recommend_by_walks(&mapping, "Sparky", 5, 50, 4, &[], &mut rng).unwrap();
+        assert_eq!(foods(&recommendations), vec!["taco"]);
+    }
+}
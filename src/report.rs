@@ -0,0 +1,240 @@
+use serde_json::{json, Value};
+use std::fs;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// How `--report` is printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(format!("expected text or json but got {:?}", s)),
+        }
+    }
+}
+
+/// Where the time went while writing an output file, and how much was written.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Each phase and how long it took, in the order they ran.
+    pub phases: Vec<(String, Duration)>,
+    /// How many nodes walks started from.
+    pub nodes_walked: u64,
+    /// Before compression.
+    pub bytes_written: u64,
+    pub lines_written: u64,
+    pub tokens_written: u64,
+    /// The most memory the process has had resident, if the OS reports it.
+    pub peak_rss_bytes: Option<u64>,
+    /// Steps from a dog to a food, and how many of them would be expected to have picked a food
+    /// the dog dislikes, as in `WalkStats`.
+    pub dog_food_steps: u64,
+    pub dislike_hits: f64,
+    /// How many of them first picked a food with an ingredient excluded for the dog.
+    pub exclusion_hits: u64,
+    /// Dogs that walks were asked for but that aren't in the graph, so were skipped.
+    pub unknown_dogs: Vec<String>,
+}
+
+impl Report {
+    /// Runs `f` as the phase called `name`.
+    pub(crate) fn time<T, F: FnOnce() -> T>(&mut self, name: &str, f: F) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.phases.push((name.to_owned(), start.elapsed()));
+        result
+    }
+
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|(_, duration)| *duration).sum()
+    }
+
+    /// How long the walk phase took, which lines and tokens per second are measured against.
+    fn walk_seconds(&self) -> f64 {
+        self.phases
+            .iter()
+            .filter(|(name, _)| name == "walk")
+            .map(|(_, duration)| duration.as_secs_f64())
+            .sum()
+    }
+
+    fn per_second(&self, count: u64) -> f64 {
+        let seconds = self.walk_seconds();
+        if seconds > 0.0 {
+            count as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    pub fn format(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Text => self.to_text(),
+            ReportFormat::Json => self.to_json().to_string(),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut lines = Vec::new();
+        for (name, duration) in &self.phases {
+            lines.push(format!("{:<24}{:>12.1}ms", name, milliseconds(*duration)));
+        }
+        lines.push(format!(
+            "{:<24}{:>12.1}ms",
+            "total",
+            milliseconds(self.total())
+        ));
+        if let Some(peak_rss_bytes) = self.peak_rss_bytes {
+            let megabytes = peak_rss_bytes as f64 / 1024.0 / 1024.0;
+            lines.push(format!("{:<24}{:>12.1}MiB", "peak RSS", megabytes));
+        }
+        lines.push(format!("{:<24}{:>12}", "nodes walked", self.nodes_walked));
+        lines.push(format!("{:<24}{:>12}", "bytes written", self.bytes_written));
+        lines.push(format!("{:<24}{:>12}", "lines written", self.lines_written));
+        lines.push(format!(
+            "{:<24}{:>12}",
+            "tokens written", self.tokens_written
+        ));
+        lines.push(format!(
+            "{:<24}{:>12.0}",
+            "lines/s",
+            self.per_second(self.lines_written)
+        ));
+        lines.push(format!(
+            "{:<24}{:>12.0}",
+            "tokens/s",
+            self.per_second(self.tokens_written)
+        ));
+        for (name, hits, precision) in [
+            ("dislike hits", self.dislike_hits, 1),
+            ("exclusion hits", self.exclusion_hits as f64, 0),
+        ] {
+            if hits > 0.0 {
+                let percent = 100.0 * hits / self.dog_food_steps as f64;
+                lines.push(format!(
+                    "{:<24}{:>12.*} ({:.2}% of dog → food steps)",
+                    name, precision, hits, percent
+                ));
+            }
+        }
+        if !self.unknown_dogs.is_empty() {
+            lines.push(format!(
+                "{:<24}{:>12}",
+                "unknown dogs",
+                self.unknown_dogs.len()
+            ));
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> Value {
+        let phases: Vec<Value> = self
+            .phases
+            .iter()
+            .map(|(name, duration)| json!({ "name": name, "ms": milliseconds(*duration) }))
+            .collect();
+        json!({
+            "phases": phases,
+            "total_ms": milliseconds(self.total()),
+            "peak_rss_bytes": self.peak_rss_bytes,
+            "nodes_walked": self.nodes_walked,
+            "bytes_written": self.bytes_written,
+            "lines_written": self.lines_written,
+            "tokens_written": self.tokens_written,
+            "lines_per_second": self.per_second(self.lines_written),
+            "tokens_per_second": self.per_second(self.tokens_written),
+            "dog_food_steps": self.dog_food_steps,
+            "dislike_hits": self.dislike_hits,
+            "exclusion_hits": self.exclusion_hits,
+            "unknown_dogs": self.unknown_dogs,
+        })
+    }
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// The process's peak resident set size, from `VmHWM` in `/proc/self/status`. `None` on
+/// systems without it.
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> Report {
+        Report {
+            phases: vec![
+                (String::from("read dog_food"), Duration::from_millis(5)),
+                (String::from("walk"), Duration::from_millis(500)),
+            ],
+            nodes_walked: 10,
+            bytes_written: 1000,
+            lines_written: 100,
+            tokens_written: 700,
+            peak_rss_bytes: Some(3 * 1024 * 1024),
+            dog_food_steps: 400,
+            dislike_hits: 10.5,
+            exclusion_hits: 4,
+            unknown_dogs: vec![String::from("Rex")],
+        }
+    }
+
+    #[test]
+    fn test_to_text() {
+        let text = report().to_text();
+        let lines: Vec<String> = text
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "read dog_food 5.0ms",
+                "walk 500.0ms",
+                "total 505.0ms",
+                "peak RSS 3.0MiB",
+                "nodes walked 10",
+                "bytes written 1000",
+                "lines written 100",
+                "tokens written 700",
+                "lines/s 200",
+                "tokens/s 1400",
+                "dislike hits 10.5 (2.62% of dog → food steps)",
+                "exclusion hits 4 (1.00% of dog → food steps)",
+                "unknown dogs 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_to_json() {
+        let json = report().to_json();
+        assert_eq!(json["phases"][1], json!({ "name": "walk", "ms": 500.0 }));
+        assert_eq!(json["total_ms"], 505.0);
+        assert_eq!(json["lines_per_second"], 200.0);
+        assert_eq!(json["tokens_per_second"], 1400.0);
+        assert_eq!(json["unknown_dogs"], json!(["Rex"]));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_peak_rss_bytes() {
+        assert!(peak_rss_bytes().unwrap() > 0);
+    }
+}
@@ -0,0 +1,539 @@
+use super::get_line::get_walk;
+use super::graph::{Graph, Neighbors};
+use super::input::{CsvOptions, InputFile};
+use super::mapped::MappedGraph;
+use super::mapping::{Delta, Mapping, NodeType, Relation};
+use super::paths;
+use super::recommend::{self, Embeddings, Recommendation};
+use serde_json::{json, Value};
+use std::sync::RwLock;
+
+/// The most walks one request can ask for.
+const MAX_WALKS: usize = 1000;
+/// How many walks, of how many cycles, to sample for recommendations without embeddings.
+const RECOMMENDATION_WALKS: usize = 256;
+const RECOMMENDATION_WALKS_PER_LINE: u8 = 4;
+
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Response { status: 200, body }
+    }
+
+    fn error(status: u16, message: String) -> Self {
+        Response {
+            status,
+            body: json!({ "error": message }),
+        }
+    }
+}
+
+/// Answers recommendation service requests from a loaded `Mapping`:
+///
+/// - `GET /dogs/{id}/walks?n=&len=` samples `n` walks of `len` cycles from a dog.
+/// - `GET /dogs/{id}/recommendations?k=&exclude=` recommends `k` foods the dog doesn't already
+///   like or avoid, and without any of the comma separated `exclude` ingredients, using
+///   embeddings if there are any for the dog and sampled walks otherwise.
+/// - `GET /dogs/{id}/explain/{food}?limit=` lists the `limit` most probable
+///   dog → food → ingredient → flavor → ingredient → food paths from the dog to the food.
+/// - `GET /{type}/{id}/{type}`, like `/foods/{id}/ingredients`, lists a node's neighbors.
+/// - `POST /updates/{file}`, like `/updates/dog_food`, applies a delta file of `+dog,food` and
+///   `-dog,food` rows with `Delta::read` and `Mapping::apply`. `/updates/dog_food_dislikes` and
+///   `/updates/dog_ingredient_exclusions` add and remove dislikes and exclusions the same way.
+///   A `MappedGraph` is read-only, so this is a 405 for those.
+///
+/// Every response is JSON. Unknown IDs and paths get a 404.
+pub struct Service {
+    graph: Backend,
+    embeddings: Option<Embeddings>,
+    csv_options: CsvOptions,
+}
+
+enum Backend {
+    Loaded(Box<RwLock<Mapping>>),
+    Mapped(MappedGraph),
+}
+
+impl Service {
+    pub fn new(mapping: Mapping, embeddings: Option<Embeddings>) -> Self {
+        Service {
+            graph: Backend::Loaded(Box::new(RwLock::new(mapping))),
+            embeddings,
+            csv_options: CsvOptions::default(),
+        }
+    }
+
+    pub fn mapped(graph: MappedGraph, embeddings: Option<Embeddings>) -> Self {
+        Service {
+            graph: Backend::Mapped(graph),
+            embeddings,
+            csv_options: CsvOptions::default(),
+        }
+    }
+
+    /// Reads delta files as described by `options` instead of as plain CSV.
+    pub fn with_csv_options(mut self, options: CsvOptions) -> Self {
+        self.csv_options = options;
+        self
+    }
+
+    pub fn handle(&self, method: &str, url: &str) -> Response {
+        self.handle_with_body(method, url, &[])
+    }
+
+    pub fn handle_with_body(&self, method: &str, url: &str, body: &[u8]) -> Response {
+        if method == "POST" {
+            return match url.strip_prefix("/updates/") {
+                Some(file) => self.update(file, body),
+                None => Response::error(405, format!("{} is not allowed", method)),
+            };
+        }
+        if method != "GET" {
+            return Response::error(405, format!("{} is not allowed", method));
+        }
+
+        let (path, query) = match url.find('?') {
+            Some(question_mark) => (&url[..question_mark], &url[question_mark + 1..]),
+            None => (url, ""),
+        };
+        let segments: Vec<String> = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(percent_decode)
+            .collect();
+        let query = Query(query);
+
+        let result = match &self.graph {
+            Backend::Loaded(mapping) => self.get(&*mapping.read().unwrap(), &segments, &query),
+            Backend::Mapped(graph) => self.get(graph, &segments, &query),
+        };
+        result.unwrap_or_else(|response| response)
+    }
+
+    fn get<G: Graph>(
+        &self,
+        graph: &G,
+        segments: &[String],
+        query: &Query,
+    ) -> Result<Response, Response> {
+        match segments {
+            [dogs, dog, walks] if dogs == "dogs" && walks == "walks" => {
+                self.walks(graph, dog, query)
+            }
+            [dogs, dog, recommendations]
+                if dogs == "dogs" && recommendations == "recommendations" =>
+            {
+                self.recommendations(graph, dog, query)
+            }
+            [dogs, dog, explain, food] if dogs == "dogs" && explain == "explain" => {
+                self.explain(graph, dog, food, query)
+            }
+            [from, id, to] => self.neighbors(graph, from, id, to),
+            _ => Err(Response::error(
+                404,
+                format!("no such path /{}", segments.join("/")),
+            )),
+        }
+    }
+
+    fn walks<G: Graph>(&self, graph: &G, dog: &str, query: &Query) -> Result<Response, Response> {
+        let n = query.get("n", 10)?;
+        let walks_per_line = query.get("len", 4)?;
+        if n > MAX_WALKS {
+            return Err(Response::error(
+                400,
+                format!("n can be at most {}", MAX_WALKS),
+            ));
+        }
+        if walks_per_line == 0 {
+            return Err(Response::error(400, String::from("len must be at least 1")));
+        }
+        known(graph, Relation::DogFood, dog)?;
+
+        let mut rng = rand::thread_rng();
+        let walks: Vec<Vec<&str>> = (0..n)
+            .map(|_| get_walk(walks_per_line, dog, graph, &mut rng))
+            .collect();
+        Ok(Response::ok(json!({ "dog": dog, "walks": walks })))
+    }
+
+    fn recommendations<G: Graph>(
+        &self,
+        graph: &G,
+        dog: &str,
+        query: &Query,
+    ) -> Result<Response, Response> {
+        let k = query.get("k", 10)?;
+        let exclude: String = query.get("exclude", String::new())?;
+        let excluded_ingredients: Vec<String> = exclude
+            .split(',')
+            .filter(|ingredient| !ingredient.is_empty())
+            .map(str::to_owned)
+            .collect();
+        known(graph, Relation::DogFood, dog)?;
+
+        let by_embeddings = self.embeddings.as_ref().and_then(|embeddings| {
+            recommend::recommend_by_embeddings(graph, embeddings, dog, k, &excluded_ingredients)
+        });
+        let recommendations: Vec<Recommendation> = match by_embeddings {
+            Some(recommendations) => recommendations,
+            None => recommend::recommend_by_walks(
+                graph,
+                dog,
+                k,
+                RECOMMENDATION_WALKS,
+                RECOMMENDATION_WALKS_PER_LINE,
+                &excluded_ingredients,
+                &mut rand::thread_rng(),
+            )
+            .unwrap_or_default(),
+        };
+        let recommendations: Vec<Value> = recommendations
+            .into_iter()
+            .map(|recommendation| {
+                json!({ "food": recommendation.food, "score": recommendation.score })
+            })
+            .collect();
+        Ok(Response::ok(
+            json!({ "dog": dog, "recommendations": recommendations }),
+        ))
+    }
+
+    fn explain<G: Graph>(
+        &self,
+        graph: &G,
+        dog: &str,
+        food: &str,
+        query: &Query,
+    ) -> Result<Response, Response> {
+        let limit = query.get("limit", 10)?;
+        known(graph, Relation::DogFood, dog)?;
+        known(graph, Relation::FoodIngredient, food)?;
+
+        let explanation = paths::explain(graph, dog, food, limit).ok_or_else(|| {
+            Response::error(404, format!("no paths from {:?} to {:?}", dog, food))
+        })?;
+        let paths: Vec<Value> = explanation
+            .paths
+            .iter()
+            .map(|path| {
+                let (shared_type, shared) = path.shared();
+                json!({
+                    "path": [
+                        dog,
+                        path.liked_food,
+                        path.liked_ingredient,
+                        path.flavor,
+                        path.ingredient,
+                        food,
+                    ],
+                    "probability": path.probability,
+                    "shared": { "type": shared_type.as_str(), "id": shared },
+                })
+            })
+            .collect();
+        Ok(Response::ok(json!({
+            "dog": dog,
+            "food": food,
+            "probability": explanation.probability,
+            "path_count": explanation.path_count,
+            "paths": paths,
+        })))
+    }
+
+    fn neighbors<G: Graph>(
+        &self,
+        graph: &G,
+        from: &str,
+        id: &str,
+        to: &str,
+    ) -> Result<Response, Response> {
+        let not_found = || Response::error(404, format!("no such path /{}/{}/{}", from, id, to));
+        let from = node_type_from_plural(from).ok_or_else(not_found)?;
+        let to = node_type_from_plural(to).ok_or_else(not_found)?;
+        let relation = Relation::between(from, to).ok_or_else(not_found)?;
+
+        let neighbors: Vec<&str> = known(graph, relation, id)?.iter().collect();
+        Ok(Response::ok(json!({ "id": id, "neighbors": neighbors })))
+    }
+
+    fn update(&self, file: &str, body: &[u8]) -> Response {
+        let input = [
+            InputFile::DogFood,
+            InputFile::FoodIngredient,
+            InputFile::IngredientFlavor,
+            InputFile::DogFoodDislikes,
+            InputFile::DogIngredientExclusions,
+        ]
+        .iter()
+        .copied()
+        .find(|input| input.to_string() == file);
+        let input = match input {
+            Some(input) => input,
+            None => return Response::error(404, format!("no such file {}", file)),
+        };
+
+        let mapping = match &self.graph {
+            Backend::Loaded(mapping) => mapping,
+            Backend::Mapped(_) => {
+                return Response::error(405, String::from("the graph is read-only"))
+            }
+        };
+        // Read the whole delta before taking the lock, so a bad body can't hold it up or
+        // poison it.
+        let delta = match Delta::read(input, body, &self.csv_options) {
+            Ok(delta) => delta,
+            Err(message) => return Response::error(400, message),
+        };
+        let applied = mapping.write().unwrap().apply(&delta);
+        Response::ok(json!({ "file": file, "applied": applied }))
+    }
+}
+
+fn known<'a, G: Graph>(
+    graph: &'a G,
+    relation: Relation,
+    id: &str,
+) -> Result<Neighbors<'a>, Response> {
+    graph
+        .neighbor_list(relation, id)
+        .ok_or_else(|| Response::error(404, format!("no {} with ID {:?}", relation.from(), id)))
+}
+
+fn node_type_from_plural(plural: &str) -> Option<NodeType> {
+    NodeType::ALL
+        .iter()
+        .copied()
+        .find(|node_type| node_type.plural() == plural)
+}
+
+struct Query<'a>(&'a str);
+
+impl<'a> Query<'a> {
+    /// The value of `name`, or `default` if it's not given.
+    fn get<T: std::str::FromStr>(&self, name: &str, default: T) -> Result<T, Response> {
+        let value = self
+            .0
+            .split('&')
+            .filter_map(|pair| {
+                let mut parts = pair.splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some(key), Some(value)) if key == name => Some(value),
+                    _ => None,
+                }
+            })
+            .next_back();
+        match value {
+            Some(value) => percent_decode(value)
+                .parse()
+                .map_err(|_| Response::error(400, format!("{} can't be {:?}", name, value))),
+            None => Ok(default),
+        }
+    }
+}
+
+/// Decodes `%XX` escapes so IDs with spaces, commas and such can be looked up. Invalid escapes
+/// are left alone.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::mapping_with;
+
+    fn service() -> Service {
+        Service::new(mapping_with("Sir Barks,taco"), None)
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let service = service();
+
+        let response = service.handle("GET", "/foods/burger/ingredients");
+        assert_eq!(
+            response,
+            Response::ok(json!({ "id": "burger", "neighbors": ["cheese", "tomato"] }))
+        );
+
+        let response = service.handle("GET", "/foods/taco/dogs");
+        assert_eq!(
+            response,
+            Response::ok(json!({ "id": "taco", "neighbors": ["Max", "Sir Barks"] }))
+        );
+
+        let response = service.handle("GET", "/dogs/Sir%20Barks/foods");
+        assert_eq!(
+            response,
+            Response::ok(json!({ "id": "Sir Barks", "neighbors": ["taco"] }))
+        );
+    }
+
+    #[test]
+    fn test_walks() {
+        let service = service();
+
+        let response = service.handle("GET", "/dogs/Max/walks?n=3&len=2");
+        assert_eq!(response.status, 200);
+        let walks = response.body["walks"].as_array().unwrap();
+        assert_eq!(walks.len(), 3);
+        for walk in walks {
+            assert_eq!(walk.as_array().unwrap().len(), 13);
+            assert_eq!(walk[0], "Max");
+        }
+    }
+
+    #[test]
+    fn test_recommendations() {
+        let service = service();
+
+        let response = service.handle("GET", "/dogs/Sparky/recommendations?k=1");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body["recommendations"][0]["food"], "taco");
+    }
+
+    #[test]
+    fn test_explain() {
+        let service = service();
+
+        let response = service.handle("GET", "/dogs/Sparky/explain/taco");
+        assert_eq!(
+            response,
+            Response::ok(json!({
+                "dog": "Sparky",
+                "food": "taco",
+                "probability": 0.0625,
+                "path_count": 1,
+                "paths": [{
+                    "path": ["Sparky", "burger", "tomato", "savory", "beef", "taco"],
+                    "probability": 0.0625,
+                    "shared": { "type": "flavor", "id": "savory" },
+                }],
+            }))
+        );
+
+        assert_eq!(service.handle("GET", "/dogs/Rex/explain/taco").status, 404);
+        assert_eq!(
+            service.handle("GET", "/dogs/Sparky/explain/kale").status,
+            404
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let service = service();
+
+        assert_eq!(service.handle("GET", "/dogs/Rex/walks").status, 404);
+        assert_eq!(
+            service.handle("GET", "/dogs/Rex/recommendations").status,
+            404
+        );
+        assert_eq!(service.handle("GET", "/foods/kale/dogs").status, 404);
+        assert_eq!(service.handle("GET", "/dogs/Max/flavors").status, 404);
+        assert_eq!(service.handle("GET", "/cats").status, 404);
+        assert_eq!(service.handle("GET", "/dogs/Max/walks?n=lots").status, 400);
+        assert_eq!(service.handle("GET", "/dogs/Max/walks?len=0").status, 400);
+        assert_eq!(service.handle("POST", "/dogs/Max/walks").status, 405);
+        assert_eq!(service.handle("POST", "/updates/dog_cat").status, 404);
+    }
+
+    #[test]
+    fn test_updates() {
+        let service = service();
+
+        let response = service.handle_with_body("POST", "/updates/dog_food", b"+Rex,pizza");
+        assert_eq!(
+            response,
+            Response::ok(json!({ "file": "dog_food", "applied": 1 }))
+        );
+        let response = service.handle("GET", "/foods/pizza/dogs");
+        assert_eq!(response.body["neighbors"], json!(["Sparky", "Rex"]));
+
+        let response = service.handle_with_body("POST", "/updates/dog_food", b"Rex,taco");
+        assert_eq!(response.status, 400);
+        assert_eq!(
+            service.handle("GET", "/dogs/Rex/foods").body["neighbors"],
+            json!(["pizza"])
+        );
+    }
+
+    #[test]
+    fn test_bad_updates_leave_the_service_up() {
+        let service = service();
+
+        for body in [&b"+Rex,\xff"[..], &b"+Rex,taco\n+Max"[..]] {
+            let response = service.handle_with_body("POST", "/updates/dog_food", body);
+            assert_eq!(response.status, 400);
+        }
+        let response = service.handle("GET", "/dogs/Sparky/foods");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body["neighbors"], json!(["burger", "pizza"]));
+        let response = service.handle_with_body("POST", "/updates/dog_food", b"+Rex,taco");
+        assert_eq!(response.status, 200);
+    }
+
+    #[test]
+    fn test_dislike_updates() {
+        let service = service();
+
+        let response =
+            service.handle_with_body("POST", "/updates/dog_food_dislikes", b"+Sparky,taco");
+        assert_eq!(
+            response,
+            Response::ok(json!({ "file": "dog_food_dislikes", "applied": 1 }))
+        );
+        let response = service.handle("GET", "/dogs/Sparky/recommendations");
+        assert_eq!(response.body["recommendations"], json!([]));
+
+        service.handle_with_body("POST", "/updates/dog_food_dislikes", b"-Sparky,taco");
+        let response = service.handle("GET", "/dogs/Sparky/recommendations?k=1");
+        assert_eq!(response.body["recommendations"][0]["food"], "taco");
+    }
+
+    #[test]
+    fn test_exclusions() {
+        let service = service();
+
+        let response = service.handle("GET", "/dogs/Sparky/recommendations?exclude=beef");
+        assert_eq!(response.body["recommendations"], json!([]));
+        let response = service.handle("GET", "/dogs/Sparky/recommendations?exclude=cheese,tomato");
+        assert_eq!(response.body["recommendations"][0]["food"], "taco");
+
+        let response = service.handle_with_body(
+            "POST",
+            "/updates/dog_ingredient_exclusions",
+            b"+Sparky,beef",
+        );
+        assert_eq!(response.status, 200);
+        let response = service.handle("GET", "/dogs/Sparky/recommendations");
+        assert_eq!(response.body["recommendations"], json!([]));
+    }
+}
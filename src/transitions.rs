@@ -0,0 +1,146 @@
+use super::graph::Graph;
+use super::mapping::{Relation, METAPATH};
+use std::collections::HashMap;
+
+/// Probabilities of being at each node of one type, keyed by ID. Nodes that can't be reached
+/// aren't in it.
+pub type Distribution<'a> = HashMap<&'a str, f64>;
+
+/// Where walks from a dog are after some number of full cycles.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CycleDistribution<'a> {
+    /// The chance the last food of the walk, the one it steps to the last dog from, is each
+    /// food. Empty after no cycles.
+    pub foods: Distribution<'a>,
+    /// The chance the walk ends at each dog.
+    pub dogs: Distribution<'a>,
+}
+
+/// The exact distribution of where a walk of `cycles` cycles from `dog` ends, for walks that
+/// pick every neighbor with equal chance like `get_walk` does. Each step multiplies the
+/// distribution by the sparse transition matrix of one relation, so it takes time in
+/// proportion to the edges reachable in `cycles` cycles instead of the whole graph. Returns
+/// `None` if `dog` isn't in the graph.
+///
+/// Probabilities are only as exact as `f64`, so they can add up to a little more or less than
+/// 1.
+///
+/// Exclusions aren't modeled: `get_walk` picks again when it steps to a food with an excluded
+/// ingredient, but here every food a dog likes keeps its share. Dislikes are, since disliked
+/// foods aren't in the graph.
+pub fn cycle_distribution<'a, G: Graph>(
+    graph: &'a G,
+    dog: &'a str,
+    cycles: usize,
+) -> Option<CycleDistribution<'a>> {
+    let foods = graph.neighbor_list(Relation::DogFood, dog)?;
+    if cycles == 0 {
+        return Some(CycleDistribution {
+            foods: Distribution::new(),
+            dogs: [(dog, 1.0)].iter().copied().collect(),
+        });
+    }
+
+    let mut distribution = Distribution::new();
+    let probability = 1.0 / foods.len() as f64;
+    for food in foods.iter() {
+        *distribution.entry(food).or_default() += probability;
+    }
+
+    let mut last_foods = Distribution::new();
+    for step in 1..cycles * METAPATH.len() {
+        let from = METAPATH[step % METAPATH.len()];
+        let to = METAPATH[(step + 1) % METAPATH.len()];
+        let relation = Relation::between(from, to).expect("consecutive metapath types are related");
+        if step == cycles * METAPATH.len() - 1 {
+            last_foods = distribution.clone();
+        }
+        distribution = transition(graph, relation, &distribution);
+    }
+
+    Some(CycleDistribution {
+        foods: last_foods,
+        dogs: distribution,
+    })
+}
+
+/// One step of a walk: `distribution` over nodes of type `relation.from()` times the
+/// transition matrix of `relation`, where each node's probability is split evenly over its
+/// neighbors.
+///
+/// # Panics
+///
+/// If a node in `distribution` isn't a node of type `relation.from()` in `graph`. Distributions
+/// from `cycle_distribution` or earlier calls only have nodes the graph has.
+pub fn transition<'a, G: Graph>(
+    graph: &'a G,
+    relation: Relation,
+    distribution: &Distribution,
+) -> Distribution<'a> {
+    let mut next = Distribution::new();
+    for (&id, &probability) in distribution {
+        let neighbors = graph
+            .neighbor_list(relation, id)
+            .expect("nodes reached by a walk are in the graph");
+        let share = probability / neighbors.len() as f64;
+        for neighbor in neighbors.iter() {
+            *next.entry(neighbor).or_default() += share;
+        }
+    }
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::mapping;
+
+    fn distribution<'a>(probabilities: &[(&'a str, f64)]) -> Distribution<'a> {
+        probabilities.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_cycle_distribution() {
+        let mapping = mapping();
+
+        assert_eq!(
+            cycle_distribution(&mapping, "Sparky", 0),
+            Some(CycleDistribution {
+                foods: Distribution::new(),
+                dogs: distribution(&[("Sparky", 1.0)]),
+            })
+        );
+
+        // Worked out by hand: every probability is a multiple of 1/64, so these are exact.
+        assert_eq!(
+            cycle_distribution(&mapping, "Sparky", 1),
+            Some(CycleDistribution {
+                foods: distribution(&[
+                    ("burger", 46.0 / 64.0),
+                    ("pizza", 14.0 / 64.0),
+                    ("taco", 4.0 / 64.0),
+                ]),
+                dogs: distribution(&[("Sparky", 37.0 / 64.0), ("Max", 27.0 / 64.0)]),
+            })
+        );
+
+        assert_eq!(cycle_distribution(&mapping, "Rex", 1), None);
+    }
+
+    #[test]
+    fn test_cycles_compose() {
+        let mapping = mapping();
+
+        let one = cycle_distribution(&mapping, "Sparky", 1).unwrap();
+        let from_max = cycle_distribution(&mapping, "Max", 1).unwrap();
+        let two = cycle_distribution(&mapping, "Sparky", 2).unwrap();
+
+        for dog in ["Sparky", "Max"] {
+            let expected =
+                one.dogs["Sparky"] * one.dogs[dog] + one.dogs["Max"] * from_max.dogs[dog];
+            assert!((two.dogs[dog] - expected).abs() < 1e-12);
+        }
+        let total: f64 = two.foods.values().sum();
+        assert!((total - 1.0).abs() < 1e-12);
+    }
+}
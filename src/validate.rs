@@ -0,0 +1,426 @@
+use super::input::{is_name, read_records, CsvOptions, InputFile};
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Read;
+
+/// Why a row was flagged. Rows that can't be read or are missing a column make `Mapping::new`
+/// panic, rows with a reachability problem are the ones it silently drops, and the rest are
+/// loaded but look wrong.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Reason {
+    Unreadable,
+    MalformedLine,
+    EmptyId,
+    Whitespace,
+    HeaderRow,
+    DuplicateHeaderRow,
+    FoodHasNoIngredients,
+    FoodIngredientsHaveNoFlavors,
+    FoodNotLikedByAnyDog,
+    IngredientHasNoFlavor,
+    IngredientNotInLikedFood,
+}
+
+impl Reason {
+    /// A short, stable identifier suitable for grepping or grouping.
+    pub fn code(self) -> &'static str {
+        match self {
+            Reason::Unreadable => "unreadable",
+            Reason::MalformedLine => "malformed_line",
+            Reason::EmptyId => "empty_id",
+            Reason::Whitespace => "whitespace",
+            Reason::HeaderRow => "header_row",
+            Reason::DuplicateHeaderRow => "duplicate_header_row",
+            Reason::FoodHasNoIngredients => "food_has_no_ingredients",
+            Reason::FoodIngredientsHaveNoFlavors => "food_ingredients_have_no_flavors",
+            Reason::FoodNotLikedByAnyDog => "food_not_liked_by_any_dog",
+            Reason::IngredientHasNoFlavor => "ingredient_has_no_flavor",
+            Reason::IngredientNotInLikedFood => "ingredient_not_in_liked_food",
+        }
+    }
+
+    /// Whether `Mapping::new` panics on the row, so the file has to be fixed before it can be
+    /// loaded.
+    pub fn is_fatal(self) -> bool {
+        matches!(self, Reason::Unreadable | Reason::MalformedLine)
+    }
+
+    /// Whether `Mapping::new` drops the row for this reason.
+    pub fn is_dropped(self) -> bool {
+        match self {
+            Reason::FoodHasNoIngredients
+            | Reason::FoodIngredientsHaveNoFlavors
+            | Reason::FoodNotLikedByAnyDog
+            | Reason::IngredientHasNoFlavor
+            | Reason::IngredientNotInLikedFood => true,
+            Reason::Unreadable
+            | Reason::MalformedLine
+            | Reason::EmptyId
+            | Reason::Whitespace
+            | Reason::HeaderRow
+            | Reason::DuplicateHeaderRow => false,
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            Reason::Unreadable => "line can't be read",
+            Reason::MalformedLine => "line is missing a column",
+            Reason::EmptyId => "ID is empty",
+            Reason::Whitespace => "ID has leading or trailing whitespace",
+            Reason::HeaderRow => "header row is loaded as data",
+            Reason::DuplicateHeaderRow => "header row after the first line",
+            Reason::FoodHasNoIngredients => "food has no ingredients",
+            Reason::FoodIngredientsHaveNoFlavors => "none of the food's ingredients have a flavor",
+            Reason::FoodNotLikedByAnyDog => "food is not liked by any dog",
+            Reason::IngredientHasNoFlavor => "ingredient has no flavor",
+            Reason::IngredientNotInLikedFood => "ingredient is not in any food a dog likes",
+        };
+        f.write_str(description)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    pub input: InputFile,
+    /// 1-based, like an editor would show it, or 0 if the problem isn't on a particular line.
+    pub line_number: usize,
+    /// The row, or for `Reason::Unreadable` rows, why it couldn't be read.
+    pub line: String,
+    pub reason: Reason,
+}
+
+struct Row {
+    line_number: usize,
+    line: String,
+    left: String,
+    right: String,
+}
+
+impl Row {
+    fn left(&self) -> &str {
+        &self.left
+    }
+
+    fn right(&self) -> &str {
+        &self.right
+    }
+}
+
+/// Reports every row `Mapping::new` would drop or panic on, and why, along with rows that are
+/// loaded but are probably not what was intended (empty IDs, stray whitespace, header rows).
+///
+/// Problems are ordered by input file and then by line number.
+pub fn validate<R: Read>(
+    dog_food_file: R,
+    food_ingredient_file: R,
+    ingredient_flavor_file: R,
+) -> Vec<Problem> {
+    validate_with_options(
+        dog_food_file,
+        food_ingredient_file,
+        ingredient_flavor_file,
+        &CsvOptions::default(),
+    )
+}
+
+/// Like `validate` but reads the files as described by `options`.
+pub fn validate_with_options<R: Read>(
+    dog_food_file: R,
+    food_ingredient_file: R,
+    ingredient_flavor_file: R,
+    options: &CsvOptions,
+) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let dog_food_rows = read_rows(InputFile::DogFood, dog_food_file, options, &mut problems);
+    let food_ingredient_rows = read_rows(
+        InputFile::FoodIngredient,
+        food_ingredient_file,
+        options,
+        &mut problems,
+    );
+    let ingredient_flavor_rows = read_rows(
+        InputFile::IngredientFlavor,
+        ingredient_flavor_file,
+        options,
+        &mut problems,
+    );
+
+    // This mirrors `Mapping::get_filtered_lines` but keeps track of what was dropped.
+    let liked_foods: HashSet<&str> = dog_food_rows.iter().map(Row::right).collect();
+    let ingredients_in_liked_foods: HashSet<&str> = food_ingredient_rows
+        .iter()
+        .filter(|row| liked_foods.contains(row.left()))
+        .map(Row::right)
+        .collect();
+
+    let mut flavored_ingredients: HashSet<&str> = HashSet::new();
+    for row in &ingredient_flavor_rows {
+        if ingredients_in_liked_foods.contains(row.left()) {
+            flavored_ingredients.insert(row.left());
+        } else {
+            problems.push(problem(
+                InputFile::IngredientFlavor,
+                row,
+                Reason::IngredientNotInLikedFood,
+            ));
+        }
+    }
+
+    let mut foods_with_ingredients: HashSet<&str> = HashSet::new();
+    let mut foods_with_flavors: HashSet<&str> = HashSet::new();
+    for row in &food_ingredient_rows {
+        foods_with_ingredients.insert(row.left());
+        if !liked_foods.contains(row.left()) {
+            problems.push(problem(
+                InputFile::FoodIngredient,
+                row,
+                Reason::FoodNotLikedByAnyDog,
+            ));
+        } else if !flavored_ingredients.contains(row.right()) {
+            problems.push(problem(
+                InputFile::FoodIngredient,
+                row,
+                Reason::IngredientHasNoFlavor,
+            ));
+        } else {
+            foods_with_flavors.insert(row.left());
+        }
+    }
+
+    for row in &dog_food_rows {
+        if !foods_with_ingredients.contains(row.right()) {
+            problems.push(problem(
+                InputFile::DogFood,
+                row,
+                Reason::FoodHasNoIngredients,
+            ));
+        } else if !foods_with_flavors.contains(row.right()) {
+            problems.push(problem(
+                InputFile::DogFood,
+                row,
+                Reason::FoodIngredientsHaveNoFlavors,
+            ));
+        }
+    }
+
+    problems.sort_by_key(|problem| (problem.input, problem.line_number));
+    problems
+}
+
+fn problem(input: InputFile, row: &Row, reason: Reason) -> Problem {
+    Problem {
+        input,
+        line_number: row.line_number,
+        line: row.line.clone(),
+        reason,
+    }
+}
+
+/// Reads every row of `file`, recording formatting problems as it goes. Only rows with both
+/// columns are returned since those are the only ones that take part in filtering.
+fn read_rows<R: Read>(
+    input: InputFile,
+    file: R,
+    options: &CsvOptions,
+    problems: &mut Vec<Problem>,
+) -> Vec<Row> {
+    let (left_name, right_name) = options.column_names(input);
+    let mut rows = Vec::new();
+
+    for record in read_records(input, file, options) {
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => {
+                problems.push(Problem {
+                    input,
+                    line_number: error.line_number,
+                    line: error.message,
+                    reason: Reason::Unreadable,
+                });
+                continue;
+            }
+        };
+        let (left, right) = match record.ids() {
+            Some((left, right)) => (left.to_owned(), right.to_owned()),
+            None => {
+                problems.push(Problem {
+                    input,
+                    line_number: record.line_number(),
+                    line: record.line(),
+                    reason: Reason::MalformedLine,
+                });
+                continue;
+            }
+        };
+        let row = Row {
+            line_number: record.line_number(),
+            line: record.line(),
+            left,
+            right,
+        };
+
+        // A header on the first line has already been skipped unless headers are turned off.
+        if is_name(&row.left, left_name) && is_name(&row.right, right_name) {
+            let reason = if row.line_number == 1 {
+                Reason::HeaderRow
+            } else {
+                Reason::DuplicateHeaderRow
+            };
+            problems.push(problem(input, &row, reason));
+        }
+        if row.left.is_empty() || row.right.is_empty() {
+            problems.push(problem(input, &row, Reason::EmptyId));
+        }
+        if row.left.trim() != row.left || row.right.trim() != row.right {
+            problems.push(problem(input, &row, Reason::Whitespace));
+        }
+
+        rows.push(row);
+    }
+
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::input::Header;
+    use super::*;
+    use std::io::BufReader;
+
+    fn validate_strs(
+        dog_food: &str,
+        food_ingredient: &str,
+        ingredient_flavor: &str,
+    ) -> Vec<Problem> {
+        validate(
+            BufReader::new(dog_food.as_bytes()),
+            BufReader::new(food_ingredient.as_bytes()),
+            BufReader::new(ingredient_flavor.as_bytes()),
+        )
+    }
+
+    fn reasons(problems: &[Problem]) -> Vec<(InputFile, usize, Reason)> {
+        problems
+            .iter()
+            .map(|problem| (problem.input, problem.line_number, problem.reason))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_clean_files() {
+        let problems = validate_strs(
+            "Sparky,burger\nMax,pizza",
+            "burger,cheese\npizza,cheese",
+            "cheese,salty",
+        );
+
+        assert_eq!(problems, vec![]);
+    }
+
+    #[test]
+    fn test_validate_reachability() {
+        let problems = validate_strs(
+            // pizza has no ingredients, taco's only ingredient has no flavor
+            "Sparky,burger\nSparky,pizza\nMax,taco",
+            // nobody likes the salad
+            "burger,cheese\ntaco,beef\nsalad,lettuce",
+            // the lettuce is only in the salad and nothing has oregano
+            "cheese,salty\nlettuce,fresh\noregano,savory",
+        );
+
+        assert_eq!(
+            reasons(&problems),
+            vec![
+                (InputFile::DogFood, 2, Reason::FoodHasNoIngredients),
+                (InputFile::DogFood, 3, Reason::FoodIngredientsHaveNoFlavors),
+                (InputFile::FoodIngredient, 2, Reason::IngredientHasNoFlavor),
+                (InputFile::FoodIngredient, 3, Reason::FoodNotLikedByAnyDog),
+                (
+                    InputFile::IngredientFlavor,
+                    2,
+                    Reason::IngredientNotInLikedFood
+                ),
+                (
+                    InputFile::IngredientFlavor,
+                    3,
+                    Reason::IngredientNotInLikedFood
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_formatting() {
+        let problems = validate_strs(
+            "Dog,Food\nSparky,burger\ndog,food\nMax\n,burger\nRex ,burger",
+            "burger,cheese",
+            "cheese,salty",
+        );
+
+        assert_eq!(
+            reasons(&problems),
+            vec![
+                (InputFile::DogFood, 3, Reason::DuplicateHeaderRow),
+                (InputFile::DogFood, 3, Reason::FoodHasNoIngredients),
+                (InputFile::DogFood, 4, Reason::MalformedLine),
+                (InputFile::DogFood, 5, Reason::EmptyId),
+                (InputFile::DogFood, 6, Reason::Whitespace),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_unreadable() {
+        let problems = validate(
+            &b"Sparky,burger\nMax,\xff\n"[..],
+            &b"burger,cheese"[..],
+            &b"cheese,salty"[..],
+        );
+        assert_eq!(
+            reasons(&problems),
+            vec![(InputFile::DogFood, 2, Reason::Unreadable)]
+        );
+        assert!(Reason::Unreadable.is_fatal() && !Reason::Unreadable.is_dropped());
+
+        let options = CsvOptions {
+            dog_food_columns: Some(("dog_id".to_owned(), "food_id".to_owned())),
+            ..CsvOptions::default()
+        };
+        let problems = validate_with_options(
+            "Sparky,burger".as_bytes(),
+            "burger,cheese".as_bytes(),
+            "cheese,salty".as_bytes(),
+            &options,
+        );
+        assert_eq!(problems[0].reason, Reason::Unreadable);
+        assert_eq!(
+            problems[0].line,
+            "no header row with columns dog_id and food_id"
+        );
+    }
+
+    #[test]
+    fn test_validate_header_without_headers() {
+        let options = CsvOptions {
+            header: Header::Absent,
+            ..CsvOptions::default()
+        };
+        let problems = validate_with_options(
+            "dog,food\nSparky,burger".as_bytes(),
+            "burger,cheese".as_bytes(),
+            "cheese,salty".as_bytes(),
+            &options,
+        );
+
+        assert_eq!(
+            reasons(&problems),
+            vec![
+                (InputFile::DogFood, 1, Reason::HeaderRow),
+                (InputFile::DogFood, 1, Reason::FoodHasNoIngredients),
+            ]
+        );
+    }
+}